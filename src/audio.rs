@@ -0,0 +1,33 @@
+use kira::{
+    manager::{backend::DefaultBackend, AudioManager, AudioManagerSettings},
+    sound::static_sound::StaticSoundData,
+};
+
+/// Thin wrapper around kira's `AudioManager` that can also be silent, so the
+/// game runs on machines without an audio device.
+pub struct Audio {
+    manager: Option<AudioManager>,
+}
+impl Audio {
+    pub fn new() -> Self {
+        match AudioManager::<DefaultBackend>::new(AudioManagerSettings::default()) {
+            Ok(manager) => Audio {
+                manager: Some(manager),
+            },
+            Err(err) => {
+                log::warn!("Couldn't start audio, ERROR: {err}");
+                Audio::silent()
+            }
+        }
+    }
+    pub fn silent() -> Self {
+        Audio { manager: None }
+    }
+    pub fn play(&mut self, sound: StaticSoundData) {
+        if let Some(manager) = &mut self.manager {
+            if let Err(err) = manager.play(sound) {
+                log::warn!("Couldn't play sound, ERROR: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use crate::{file::save_png, surface::Surface};
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Saves `screen` as the first free `screenshots/screenshot_NNNN.png`.
+pub fn save_screenshot(screen: &Surface) {
+    let path = (0..)
+        .map(|i| format!("{SCREENSHOT_DIR}/screenshot_{i:04}.png"))
+        .find(|path| !Path::new(path).exists())
+        .unwrap();
+    match save_png(screen, &path) {
+        Ok(()) => log::info!("Saved screenshot to {path}"),
+        Err(err) => log::warn!("Couldn't save screenshot to {path}, ERROR: {err}"),
+    }
+}
+
+/// Writes consecutive frames to numbered PNGs in `dir`.
+pub struct FrameRecorder {
+    dir: String,
+    frames: usize,
+    remaining: usize,
+    next_index: usize,
+}
+impl FrameRecorder {
+    /// Numbering starts at the first free index in `dir` so captures from
+    /// earlier runs aren't overwritten.
+    pub fn new(dir: &str, frames: usize) -> Self {
+        let next_index = (0..)
+            .find(|i| !Path::new(&format!("{dir}/frame_{i:05}.png")).exists())
+            .unwrap();
+        FrameRecorder {
+            dir: dir.to_string(),
+            frames,
+            remaining: 0,
+            next_index,
+        }
+    }
+    pub fn is_recording(&self) -> bool {
+        self.remaining > 0
+    }
+    /// Starts recording the next `frames` frames. Numbering carries on from
+    /// the previous recording so earlier captures aren't overwritten.
+    pub fn start(&mut self) {
+        log::info!("Recording {} frames to {}", self.frames, self.dir);
+        self.remaining = self.frames;
+    }
+    pub fn capture(&mut self, screen: &Surface) {
+        if !self.is_recording() {
+            return;
+        }
+        let path = format!("{}/frame_{:05}.png", self.dir, self.next_index);
+        if let Err(err) = save_png(screen, &path) {
+            log::warn!("Couldn't save {path}, stopping recording. ERROR: {err}");
+            self.remaining = 0;
+            return;
+        }
+        self.next_index += 1;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            log::info!("Finished recording to {}", self.dir);
+        }
+    }
+}
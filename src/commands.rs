@@ -0,0 +1,95 @@
+use crate::{
+    components::Component,
+    entity::{Entities, Entity},
+};
+
+/// Changes to the set of entities queued while entities update. They are
+/// applied in one go at the end of the tick, so nothing appears, disappears
+/// or changes its components half way through a tick.
+#[derive(Default)]
+pub struct Commands<'a> {
+    spawns: Vec<Entity<'a>>,
+    components: Vec<(u32, Box<dyn Component>)>,
+    despawns: Vec<u32>,
+}
+impl<'a> Commands<'a> {
+    /// `entity` has to have its id reserved already.
+    pub fn spawn(&mut self, entity: Entity<'a>) {
+        self.spawns.push(entity);
+    }
+    pub fn add_component(&mut self, id: u32, component: Box<dyn Component>) {
+        self.components.push((id, component));
+    }
+    pub fn despawn(&mut self, id: u32) {
+        self.despawns.push(id);
+    }
+    /// Spawns first and despawns last, so components can be added to
+    /// entities spawned in the same tick.
+    pub fn apply(&mut self, entities: &mut Entities<'a>) {
+        for entity in self.spawns.drain(..) {
+            entities.insert(entity);
+        }
+        for (id, component) in self.components.drain(..) {
+            if let Some(entity) = entities.get_mut(id) {
+                entity.add_component(component);
+            }
+        }
+        for id in self.despawns.drain(..) {
+            entities.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        components::{Component, HealthComponent},
+        entity::Entity,
+        tile_map::{test_entity, test_game},
+        Game, TICK,
+    };
+
+    /// Gives its own entity a `HealthComponent` on its first update.
+    struct Adder;
+    impl Component for Adder {
+        fn update<'a>(&mut self, entity: &mut Entity<'a>, game: &mut Game<'a>, _dt: f32) {
+            if !entity.has::<HealthComponent>() {
+                game.add_component(entity.id, Box::new(HealthComponent { hp: 2 }));
+            }
+        }
+    }
+
+    fn health(game: &Game, id: u32) -> Option<i32> {
+        let entity = game.entities.get(id).unwrap();
+        entity.get::<HealthComponent>().map(|health| health.hp)
+    }
+
+    #[test]
+    fn adds_components_at_the_end_of_the_tick() {
+        let mut game = test_game();
+        let id = game.entities.add(test_entity(vec![Box::new(Adder)]));
+        assert_eq!(health(&game, id), None);
+        game.tick(TICK);
+        assert_eq!(health(&game, id), Some(2));
+    }
+
+    #[test]
+    fn adds_components_to_entities_spawned_the_same_tick() {
+        let mut game = test_game();
+        let id = game.spawn(test_entity(Vec::new()));
+        game.add_component(id, Box::new(HealthComponent { hp: 5 }));
+        assert!(game.entities.get(id).is_none());
+        game.tick(TICK);
+        assert_eq!(health(&game, id), Some(5));
+    }
+
+    #[test]
+    fn drops_components_for_entities_despawned_the_same_tick() {
+        let mut game = test_game();
+        let id = game.entities.add(test_entity(Vec::new()));
+        game.add_component(id, Box::new(HealthComponent { hp: 5 }));
+        game.despawn(id);
+        game.tick(TICK);
+        assert!(game.entities.get(id).is_none());
+    }
+}
@@ -1,10 +1,19 @@
-use super::Component;
+use super::{Component, PathFollowComponent, PlayerInputComponent};
 
+/// Goes for the player, along a path if the entity has a
+/// `PathFollowComponent` and straight at them otherwise.
 pub struct BasicAiComponent;
 
 impl Component for BasicAiComponent {
     fn update(&mut self, entity: &mut crate::entity::Entity, game: &mut crate::Game, _dt: f32) {
-        entity.vel = (game.entities.get(&0).unwrap().rect.pos - entity.rect.pos).normalize() * 0.5;
-        //println!("{:?}, {:?}", entity.pos, entity.rect.pos);
+        let Some((player, _)) = game.entities.with::<PlayerInputComponent>().next() else {
+            return;
+        };
+        let target = player.rect.pos;
+        if let Some(follow) = entity.get_mut::<PathFollowComponent>() {
+            follow.target = Some(target);
+        } else {
+            entity.vel = (target - entity.rect.pos).normalize() * 0.5;
+        }
     }
 }
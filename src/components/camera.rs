@@ -1,13 +1,19 @@
 use super::Component;
-use crate::math::set_value_brightness;
+use crate::lighting::Lighting;
 use crate::tile_map::*;
-use crate::{depth_buffer::*, entity::Entity, Game};
+use crate::{depth_buffer::*, entity::Entity, surface::Surface, AssetCache, Game};
 use glam::*;
 
+use std::f32::consts::TAU;
+use std::iter;
 use std::sync::{mpsc, Arc};
 
 
 use threadpool::ThreadPool;
+
+/// How far the camera can look up or down, in radians.
+pub const MAX_PITCH: f32 = 0.4;
+
 pub struct CameraComponent {
     threadpool: ThreadPool,
 }
@@ -17,15 +23,30 @@ impl CameraComponent {
             threadpool: ThreadPool::new(4),
         }
     }
+    /// The baked lights of the map plus every entity light, including the
+    /// camera's own entity which isn't in `game.entities` while it renders.
+    fn lighting(&self, entity: &Entity, game: &Game) -> Lighting {
+        let dynamic = game
+            .entities
+            .iter()
+            .chain(iter::once(entity))
+            .filter_map(|other| other.light.map(|light| light.at(other.rect.pos)))
+            .collect();
+        Lighting {
+            light_map: Arc::clone(&game.tile_map.light_map),
+            dynamic: Arc::new(dynamic),
+        }
+    }
     fn project_entities<'a>(
         &self,
         entity: &Entity,
         game: &mut Game<'a>,
         camera_plane: Vec2,
         camera_normal: Vec2,
+        lighting: &Lighting,
     ) {
         let transform_mat = Mat2::from_cols(camera_plane, camera_normal);
-        for other in game.entities.values() {
+        for other in game.entities.iter() {
             if let Some(sprite) = other.sprite {
                 let enemy_offset_pos = other.rect.pos - entity.rect.pos;
                 let enemy_projected_pos = transform_mat.mul_vec2(enemy_offset_pos);
@@ -36,6 +57,7 @@ impl CameraComponent {
                     game.renderer.data.push(DepthBufferData {
                         distance: enemy_projected_pos.y,
                         column,
+                        light: lighting.at(other.rect.pos),
                         data_type: BufferDataType::Sprite { surf: sprite },
                     });
                 }
@@ -43,14 +65,25 @@ impl CameraComponent {
         }
     }
 
-    fn cast_rays(&self, entity: &Entity, game: &mut Game, camera_plane: Vec2, camera_normal: Vec2) {
+    fn cast_rays(
+        &self,
+        entity: &Entity,
+        game: &mut Game,
+        camera_plane: Vec2,
+        camera_normal: Vec2,
+        lighting: &Lighting,
+    ) -> Vec<f32> {
+        game.renderer.clear_z_buffer(game.screen.width);
         let ray_start = entity.rect.pos;
+        let max_top = game.tile_map.max_top();
+        let top_slope = game.renderer.horizon as f32 / game.screen.height as f32;
         let rays: Vec<Vec2> = (0..game.screen.width)
             .map(|i| {
                 let cam_x = (2 * i) as f32 / game.screen.width as f32 - 1.0;
                 camera_normal + camera_plane * cam_x
             })
             .collect();
+        let mut sky_distances = vec![f32::INFINITY; rays.len()];
 
         for (index, &ray_dir) in rays.iter().enumerate() {
             let ray_unit_step = Vec2::new((1.0 / ray_dir.x).abs(), (1.0 / ray_dir.y).abs());
@@ -74,12 +107,22 @@ impl CameraComponent {
                 ray_length_1d.y = (map_check.y as f32 + 1.0 - ray_start.y) * ray_unit_step.y;
             }
 
-            let mut tile_found = false;
+            // slope from the eye to the highest top of the floor standing
+            // walls hit so far, everything behind below it is hidden
+            let mut cover = f32::NEG_INFINITY;
+            let mut covered = false;
             let mut direction;
             let max_steps = 100;
             let mut steps = 0;
 
-            while !tile_found && steps < max_steps {
+            while steps < max_steps {
+                // stop once no wall further out can show above the cover,
+                // or the cover reaches the top of the screen
+                let next_distance = ray_length_1d.min_element();
+                if cover >= (max_top - 0.5) / next_distance || cover >= top_slope {
+                    covered = true;
+                    break;
+                }
                 if ray_length_1d.x < ray_length_1d.y {
                     ray_length_1d.x += ray_unit_step.x;
                     map_check.x += step.x;
@@ -97,7 +140,7 @@ impl CameraComponent {
                     };
                     let mut tex_offset = 0.0;
                     match &tile.tile_type {
-                        TileType::Wall => tile_found = true,
+                        TileType::Wall => {}
                         TileType::Door(open_amount, door_dir) => {
                             if &direction == door_dir {
                                 tex_offset = *open_amount;
@@ -145,9 +188,21 @@ impl CameraComponent {
                     if percentage > 1.0 {
                         continue;
                     }
+                    // subwalls are see-through, sprites behind them still
+                    // get drawn and sorted against them per column
+                    if !matches!(tile.tile_type, TileType::Subwall(..))
+                        && tile.elevation <= 0.0
+                        && tile.top() >= 1.0
+                    {
+                        let z = &mut game.renderer.z_buffer[index];
+                        *z = z.min(distance);
+                    }
                     game.renderer.data.push(DepthBufferData {
                         distance,
                         column: index as i32,
+                        // sample just in front of the wall, inside the cell
+                        // the light is coming from
+                        light: lighting.at(intersection - ray_dir.normalize() * 0.05),
                         data_type: BufferDataType::Wall {
                             direction,
                             percentage,
@@ -155,85 +210,116 @@ impl CameraComponent {
                                 Direction::Horizontal => 0,
                                 Direction::Vertical => 1,
                             }],
+                            elevation: tile.elevation,
+                            height: tile.height,
                         },
                     });
+                    if tile.occludes() {
+                        cover = cover.max((tile.top() - 0.5) / distance);
+                    }
                 }
                 steps += 1;
             }
+            if !covered {
+                sky_distances[index] = ray_length_1d.min_element();
+            }
         }
+        sky_distances
     }
+    /// Draws the floor and the ceiling, or the sky for cells without a
+    /// ceiling and past `sky_distances` in columns the rays escaped through.
     fn cast_floor(
         &self,
         entity: &Entity,
         game: &mut Game,
         camera_plane: Vec2,
         camera_normal: Vec2,
+        lighting: &Lighting,
+        sky_distances: Vec<f32>,
     ) {
-        let floor_tex = game.assets.load_png("assets/ceil.png");
-        let ceil_tex = game.assets.load_png("assets/floor.png");
-        let floor_size = floor_tex.width as f32;
+        let surfaces = Arc::new(SurfaceLookup::new(&game.tile_map, &game.assets));
+        let sky = Arc::new(Sky::new(
+            &game.tile_map,
+            &game.assets,
+            camera_plane,
+            camera_normal,
+            game.screen.width,
+            sky_distances,
+        ));
         let ray_dir0 = camera_normal - camera_plane;
         let ray_dir1 = camera_normal + camera_plane;
 
         let pos_z = 0.5 * game.screen.height as f32;
+        let horizon = game.renderer.horizon;
 
         let (tx, rx) = mpsc::channel();
 
         let num_jobs: usize = game.screen.height / 16;
-        let y_per_job = game.screen.height / 2 / num_jobs;
-        let tex_width = floor_tex.width;
+        let y_per_job = game.screen.height.div_ceil(num_jobs);
         let screen_width = game.screen.width;
         let screen_height = game.screen.height;
         let pos = entity.rect.pos;
 
         for t in 0..num_jobs {
             let tx = tx.clone();
-            let start_i = game.screen.height / 2 + t * y_per_job;
-            let end_i = start_i + y_per_job;
-            let floor_tex = Arc::clone(&floor_tex);
-            let ceil_tex = Arc::clone(&ceil_tex);
+            let start_i = t * y_per_job;
+            let end_i = (start_i + y_per_job).min(screen_height);
+            let surfaces = Arc::clone(&surfaces);
+            let sky = Arc::clone(&sky);
+            let lighting = lighting.clone();
+            let fog = game.tile_map.fog;
 
             self.threadpool.execute(move || {
                 for y in (start_i)..end_i {
-                    let p = y - screen_height / 2;
+                    // rows above the horizon are the ceiling, mirrored
+                    // around it
+                    let ceiling = (y as i32) < horizon;
+                    let p = if ceiling {
+                        horizon - 1 - y as i32
+                    } else {
+                        y as i32 - horizon
+                    };
                     let row_dist = pos_z / p as f32;
                     let floor_step = row_dist * (ray_dir1 - ray_dir0) / screen_width as f32;
 
                     let mut floor_pos = pos + row_dist * ray_dir0;
                     let mut vals = Vec::with_capacity(screen_width);
 
-                    let brightness = ((1.0 / row_dist.sqrt() + 0.2).min(1.0) * 255.0) as u32;
-
-                    for _ in 0..screen_width {
-                        let tex_pos = (floor_size * floor_pos.fract()).as_uvec2();
+                    // the panorama moves with the horizon
+                    let sky_y = (y as i32 - horizon) as f32 / (screen_height / 2) as f32 + 1.0;
+                    for x in 0..screen_width {
+                        let (floor_tex, ceil_tex) = surfaces.at(floor_pos);
+                        let light = Vec3::ONE + lighting.at(floor_pos);
+                        let sample = |tex: &Surface| {
+                            let tex_pos = (vec2(tex.width as f32, tex.height as f32)
+                                * floor_pos.fract())
+                            .as_uvec2();
+                            let index = tex_pos.x as usize + tex_pos.y as usize * tex.width;
+                            fog.shade(
+                                *tex.pixel_buffer.get(index).unwrap_or(&0u32),
+                                light,
+                                row_dist,
+                            )
+                        };
+                        vals.push(if ceiling {
+                            ceil_tex
+                                .filter(|_| row_dist < sky.distances[x])
+                                .map_or_else(|| sky.at(x, sky_y), sample)
+                        } else {
+                            sample(floor_tex)
+                        });
                         floor_pos += floor_step;
-                        let index = tex_pos.x as usize + tex_pos.y as usize * tex_width;
-                        vals.push((
-                            y as u16,
-                            set_value_brightness(
-                                *floor_tex.pixel_buffer.get(index as usize).unwrap_or(&0u32),
-                                brightness,
-                            ),
-                            set_value_brightness(
-                                *ceil_tex.pixel_buffer.get(index as usize).unwrap_or(&0u32),
-                                brightness,
-                            ),
-                        ));
                     }
-                    tx.send(vals).unwrap();
+                    tx.send((y, vals)).unwrap();
                 }
             });
         }
         drop(tx);
         //let start = time::Instant::now();
 
-        for val in rx {
-            for (x, (y, col1, col2)) in val.into_iter().enumerate() {
-                game.screen.pixel_buffer[x as usize + y as usize * game.screen.width] = col1;
-
-                game.screen.pixel_buffer
-                    [x as usize + (game.screen.height - 1 - y as usize) * game.screen.width] = col2;
-            }
+        for (y, vals) in rx {
+            let start = y * game.screen.width;
+            game.screen.pixel_buffer[start..start + game.screen.width].copy_from_slice(&vals);
         }
         /*
         log::info!(
@@ -243,12 +329,126 @@ impl CameraComponent {
     }
 }
 
+/// The sky as seen by each screen column.
+struct Sky {
+    texture: Option<Arc<Surface>>,
+    /// Fills in for a missing texture.
+    color: u32,
+    /// Texture column seen by each screen column.
+    columns: Vec<usize>,
+    /// Distance after which each screen column only sees sky.
+    distances: Vec<f32>,
+}
+impl Sky {
+    fn new(
+        tile_map: &TileMap,
+        assets: &AssetCache,
+        camera_plane: Vec2,
+        camera_normal: Vec2,
+        screen_width: usize,
+        distances: Vec<f32>,
+    ) -> Self {
+        let texture = tile_map.sky_texture.map(|path| assets.load_png(path));
+        let tex_width = texture.as_ref().map_or(1, |texture| texture.width);
+        let columns = (0..screen_width)
+            .map(|x| {
+                let cam_x = (2 * x) as f32 / screen_width as f32 - 1.0;
+                let ray_dir = camera_normal + camera_plane * cam_x;
+                let turns = ray_dir.y.atan2(ray_dir.x) / TAU;
+                (turns.rem_euclid(1.0) * tex_width as f32) as usize % tex_width
+            })
+            .collect();
+        Sky {
+            texture,
+            color: tile_map.fog.shade(0, Vec3::ZERO, f32::INFINITY),
+            columns,
+            distances,
+        }
+    }
+    /// `y` goes from 0 at the top of the screen to 1 at the horizon.
+    fn at(&self, x: usize, y: f32) -> u32 {
+        match &self.texture {
+            Some(texture) => {
+                let tex_y = ((y * texture.height as f32) as usize).min(texture.height - 1);
+                texture.pixel_buffer[self.columns[x] + tex_y * texture.width]
+            }
+            None => self.color,
+        }
+    }
+}
+
+/// Floor and ceiling textures of every cell, looked up once per frame so the
+/// floor casting jobs don't need the tile map.
+struct SurfaceLookup {
+    width: usize,
+    height: usize,
+    /// Indices into `textures`, a `None` ceiling is open sky.
+    cells: Vec<(usize, Option<usize>)>,
+    outside: (usize, Option<usize>),
+    textures: Vec<Arc<Surface>>,
+}
+impl SurfaceLookup {
+    fn new<'a>(tile_map: &TileMap<'a>, assets: &AssetCache) -> Self {
+        let mut paths: Vec<&'a str> = Vec::new();
+        let mut index_of = |surfaces: Surfaces<'a>| {
+            let mut index = |path| match paths.iter().position(|&p| p == path) {
+                Some(i) => i,
+                None => {
+                    paths.push(path);
+                    paths.len() - 1
+                }
+            };
+            (index(surfaces.floor), surfaces.ceiling.map(index))
+        };
+        let cells = (0..tile_map.height as i32)
+            .flat_map(|y| (0..tile_map.width as i32).map(move |x| ivec2(x, y)))
+            .map(|pos| index_of(tile_map.get_surfaces(pos)))
+            .collect();
+        let outside = index_of(tile_map.get_surfaces(IVec2::NEG_ONE));
+        SurfaceLookup {
+            width: tile_map.width,
+            height: tile_map.height,
+            cells,
+            outside,
+            textures: paths.iter().map(|path| assets.load_png(path)).collect(),
+        }
+    }
+    fn at(&self, pos: Vec2) -> (&Surface, Option<&Surface>) {
+        let cell = pos.floor().as_ivec2();
+        let (floor, ceiling) = if 0 <= cell.x
+            && cell.x < self.width as i32
+            && 0 <= cell.y
+            && cell.y < self.height as i32
+        {
+            self.cells[cell.x as usize + cell.y as usize * self.width]
+        } else {
+            self.outside
+        };
+        (
+            &self.textures[floor],
+            ceiling.map(|ceiling| self.textures[ceiling].as_ref()),
+        )
+    }
+}
+
 impl Component for CameraComponent {
-    fn update<'a>(&mut self, entity: &mut Entity, game: &mut Game, _dt: f32) {
+    fn update<'a>(&mut self, _entity: &mut Entity, _game: &mut Game, _dt: f32) {}
+    fn render<'a>(&mut self, entity: &Entity<'a>, game: &mut Game<'a>) {
         let camera_plane = Vec2::new(1.0, 0.0).rotate(Vec2::from_angle(entity.look_angle));
         let camera_normal = Vec2::new(camera_plane.y, -camera_plane.x);
-        self.cast_rays(entity, game, camera_plane, camera_normal);
-        self.project_entities(entity, game, camera_plane, camera_normal);
-        self.cast_floor(entity, game, camera_plane, camera_normal)
+        // looking up or down shears the view instead of tilting it
+        game.renderer.horizon = (game.screen.height / 2) as i32
+            + (entity.look_pitch.tan() * game.screen.height as f32) as i32;
+        let lighting = self.lighting(entity, game);
+        let sky_distances = self.cast_rays(entity, game, camera_plane, camera_normal, &lighting);
+        self.project_entities(entity, game, camera_plane, camera_normal, &lighting);
+        self.cast_floor(
+            entity,
+            game,
+            camera_plane,
+            camera_normal,
+            &lighting,
+            sky_distances,
+        )
     }
 }
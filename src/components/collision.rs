@@ -1,29 +1,17 @@
-use glam::{vec2, Vec2};
+use crate::{entity::Entity, events::Event, rect::Rect, Game};
 
-use crate::{entity::Entity, Game};
-
-use super::{AnimationComponent, Component};
+use super::Component;
 pub struct BasicCollisionComponent;
 impl Component for BasicCollisionComponent {
     fn update<'a>(&mut self, entity: &mut Entity, game: &mut Game, dt: f32) {
-        let old_rect = entity.rect.clone();
+        let old_rect = entity.rect;
 
         entity.rect.pos.x += entity.vel.x * dt;
 
-        let cols = game.tile_map.get_collisions(&entity.rect);
-
-        for col in game
-            .entities
-            .values()
-            .filter_map(|other| {
-                let rect = other.rect;
-                (entity.rect.collide(&rect) && other.collidable).then_some(rect)
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .chain(cols.into_iter())
-        {
+        let mut hits = Vec::new();
+        for (other, col) in contacts(entity, game) {
             {
+                hits.push(other);
                 if entity.rect.get_right() >= col.get_left()
                     && old_rect.get_right() <= col.get_left()
                 {
@@ -37,20 +25,10 @@ impl Component for BasicCollisionComponent {
         }
 
         entity.rect.pos.y += entity.vel.y * dt;
-        let cols = game.tile_map.get_collisions(&entity.rect);
 
-        for col in game
-            .entities
-            .values()
-            .filter_map(|other| {
-                let rect = other.rect;
-                (entity.rect.collide(&rect) && other.collidable).then_some(rect)
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .chain(cols.into_iter())
-        {
+        for (other, col) in contacts(entity, game) {
             {
+                hits.push(other);
                 if entity.rect.get_bottom() >= col.get_top()
                     && old_rect.get_bottom() <= col.get_top()
                 {
@@ -62,8 +40,37 @@ impl Component for BasicCollisionComponent {
                 }
             }
         }
+
+        hits.sort();
+        hits.dedup();
+        for other in hits {
+            game.emit(Event::Collided {
+                entity: entity.id,
+                other,
+            });
+        }
     }
 }
+
+/// The collidable entities and the tiles `entity` overlaps, entities with
+/// their id and tiles with `None`.
+fn contacts(entity: &Entity, game: &Game) -> Vec<(Option<u32>, Rect)> {
+    game.entities
+        .iter()
+        .filter(|other| other.id != entity.id)
+        .filter_map(|other| {
+            let rect = other.rect;
+            (entity.rect.collide(&rect) && other.collidable).then_some((Some(other.id), rect))
+        })
+        .chain(
+            game.tile_map
+                .get_collisions(&entity.rect)
+                .into_iter()
+                .map(|rect| (None, rect)),
+        )
+        .collect()
+}
+
 pub struct ProjectileCollisionComponent {
     owner_id: u32,
 }
@@ -74,56 +81,38 @@ impl ProjectileCollisionComponent {
 }
 impl Component for ProjectileCollisionComponent {
     fn update<'a>(&mut self, entity: &mut Entity, game: &mut Game<'a>, dt: f32) {
-        let mut new_rect = entity.rect.clone();
+        let mut new_rect = entity.rect;
         new_rect.pos = entity.rect.pos + entity.vel * dt;
 
-        let mut collided = false;
-        for other in game.entities.values_mut() {
-            if other.id != self.owner_id && other.rect.collide(&new_rect) {
-                collided = true;
-                other.health -= 1;
-                break;
-            }
-        }
-        if !game.tile_map.get_collisions(&new_rect).is_empty() {
-            collided = true;
+        let hit = game
+            .entities
+            .iter()
+            .find(|other| {
+                other.id != self.owner_id && other.id != entity.id && other.rect.collide(&new_rect)
+            })
+            .map(|other| other.id);
+        if let Some(target) = hit {
+            game.emit(Event::Damaged {
+                target,
+                source: self.owner_id,
+                amount: 1,
+            });
         }
+        let collided = hit.is_some() || !game.tile_map.get_collisions(&new_rect).is_empty();
         if collided {
+            game.emit(Event::Collided {
+                entity: entity.id,
+                other: hit,
+            });
             let explosion_sound = game
                 .assets
                 .load_sound("assets/sounds/explosionCrunch_000.ogg", None);
-            entity.alive = false;
-            game.audio_manager.play(explosion_sound).unwrap();
+            game.despawn(entity.id);
+            game.audio.play(explosion_sound);
 
-            let images = vec![
-                "assets/explosion/explosion1.png",
-                "assets/explosion/explosion2.png",
-                "assets/explosion/explosion3.png",
-                "assets/explosion/explosion4.png",
-                "assets/explosion/explosion5.png",
-                "assets/explosion/explosion6.png",
-                "assets/explosion/explosion7.png",
-                "assets/explosion/explosion8.png",
-                "assets/explosion/explosion9.png",
-                "assets/explosion/explosion10.png",
-                "assets/explosion/explosion11.png",
-                "assets/explosion/explosion12.png",
-            ];
-            game.add_entity(Entity::new(
-                entity.rect.pos,
-                Some(images[0]),
-                vec2(0.0, 0.0),
-                1.0,
-                false,
-                vec![
-                    Box::new(AnimationComponent {
-                        images,
-                        time_per_frame: 0.05,
-                        cur_time: 0.0,
-                    }),
-                    Box::new(BasicCollisionComponent),
-                ],
-            ))
+            if let Err(err) = game.spawn_prefab("explosion", entity.rect.pos) {
+                log::warn!("Couldn't spawn an explosion, ERROR: {err}");
+            }
         }
         entity.rect = new_rect;
     }
@@ -1,4 +1,5 @@
 use super::Component;
+use crate::events::Event;
 
 pub struct DeathComponent {
     death_sound: &'static str,
@@ -9,10 +10,11 @@ impl DeathComponent {
     }
 }
 impl Component for DeathComponent {
-    fn update<'a>(&mut self, entity: &mut crate::entity::Entity<'a>, game: &mut crate::Game<'a>, dt: f32) {
-        if entity.health <= 0 {
-            entity.alive = false;
-            game.audio_manager.play(game.assets.load_sound(self.death_sound, None)).unwrap();
+    fn update<'a>(&mut self, _entity: &mut crate::entity::Entity<'a>, _game: &mut crate::Game<'a>, _dt: f32) {}
+    fn on_event<'a>(&mut self, entity: &mut crate::entity::Entity<'a>, game: &mut crate::Game<'a>, event: &Event) {
+        if *event == (Event::Died { entity: entity.id }) {
+            game.despawn(entity.id);
+            game.audio.play(game.assets.load_sound(self.death_sound, None));
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,592 @@
+use glam::*;
+use serde::Deserialize;
+
+use super::{fire_projectile, Component, FactionComponent, HealthComponent, PathFollowComponent};
+use crate::{entity::Entity, events::Event, Game};
+
+/// Radians per second an alerted enemy turns while looking around.
+const LOOK_AROUND_SPEED: f32 = 1.5;
+/// How far ahead a fleeing enemy looks for somewhere to run to.
+const FLEE_DISTANCE: f32 = 4.0;
+/// How close counts as having arrived somewhere.
+const ARRIVED: f32 = 0.5;
+/// Walking speed of an enemy that came without a `PathFollowComponent`.
+const WALK_SPEED: f32 = 0.5;
+
+/// How an `EnemyAiComponent` perceives and fights.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct AiSettings {
+    /// How far it can see, in tiles.
+    pub view_distance: f32,
+    /// Width of the vision cone, in radians. Targets closer than a tile are
+    /// noticed from any side.
+    pub view_angle: f32,
+    /// How far away it hears gunfire.
+    pub hearing_distance: f32,
+    /// Seconds it remembers where it last saw a target.
+    pub memory: f32,
+    /// Seconds it searches where it heard or last saw something.
+    pub alert_time: f32,
+    /// How close it gets before it starts shooting.
+    pub attack_distance: f32,
+    /// Seconds between shots.
+    pub fire_cooldown: f32,
+    /// Runs away at this many hit points or fewer, 0 never runs.
+    pub flee_hp: i32,
+}
+impl Default for AiSettings {
+    fn default() -> Self {
+        AiSettings {
+            view_distance: 8.0,
+            view_angle: 2.0,
+            hearing_distance: 10.0,
+            memory: 5.0,
+            alert_time: 4.0,
+            attack_distance: 5.0,
+            fire_cooldown: 1.0,
+            flee_hp: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AiState {
+    Idle,
+    /// Walking to the patrol point with this index.
+    Patrol(usize),
+    /// Searching around `pos` until `time_left` runs out.
+    Alert {
+        pos: Vec2,
+        time_left: f32,
+    },
+    /// Going after the remembered target.
+    Chase,
+    /// Standing and shooting at a target in sight.
+    Attack,
+    /// Running from the remembered target.
+    Flee,
+}
+
+/// Where a target was last seen and how many seconds ago.
+#[derive(Clone, Copy)]
+struct Memory {
+    pos: Vec2,
+    age: f32,
+}
+
+/// Enemy behaviour as a state machine. Looks for entities of hostile
+/// factions in its vision cone, hears `Fired` events and notices who
+/// damages it. Walks with the entity's `PathFollowComponent` and gives it one
+/// if it has none.
+pub struct EnemyAiComponent {
+    pub settings: AiSettings,
+    pub patrol: Vec<Vec2>,
+    pub state: AiState,
+    memory: Option<Memory>,
+    /// Seconds until it can shoot again.
+    cooldown: f32,
+}
+impl EnemyAiComponent {
+    pub fn new(settings: AiSettings, patrol: Vec<Vec2>) -> Self {
+        EnemyAiComponent {
+            settings,
+            patrol,
+            state: AiState::Idle,
+            memory: None,
+            cooldown: 0.0,
+        }
+    }
+
+    /// Position of the nearest hostile entity with hit points left that is
+    /// in the vision cone and in line of sight.
+    fn look(&self, entity: &Entity, game: &Game) -> Option<Vec2> {
+        let own = entity.get::<FactionComponent>();
+        let pos = entity.rect.pos;
+        let facing = facing(entity.look_angle);
+        game.entities
+            .with2::<FactionComponent, HealthComponent>()
+            .filter(|(other, faction, health)| {
+                other.id != entity.id
+                    && FactionComponent::is_hostile(own, faction)
+                    && health.hp > 0
+            })
+            .map(|(other, _, _)| other.rect.pos)
+            .filter(|&other| {
+                let to = other - pos;
+                let distance = to.length();
+                distance <= self.settings.view_distance
+                    && (distance < 1.0
+                        || facing.angle_between(to).abs() <= self.settings.view_angle / 2.0)
+                    && game.line_of_sight(pos, other)
+            })
+            .min_by(|a, b| a.distance(pos).total_cmp(&b.distance(pos)))
+    }
+
+    /// Whether the entity `id` is of a faction hostile to `entity`.
+    fn is_hostile(entity: &Entity, game: &Game, id: u32) -> bool {
+        game.entities
+            .get(id)
+            .and_then(|other| other.get::<FactionComponent>())
+            .is_some_and(|faction| {
+                FactionComponent::is_hostile(entity.get::<FactionComponent>(), faction)
+            })
+    }
+
+    /// Patrol if there is a route, idle otherwise.
+    fn calm_state(&self) -> AiState {
+        if self.patrol.is_empty() {
+            AiState::Idle
+        } else {
+            AiState::Patrol(0)
+        }
+    }
+
+    /// A walkable spot away from `threat`, straight away if possible and
+    /// turning further aside otherwise.
+    fn flee_point(pos: Vec2, threat: Vec2, game: &Game) -> Option<Vec2> {
+        let away = (pos - threat).normalize_or_zero();
+        [0.0, 0.5, -0.5, 1.0, -1.0, 1.5, -1.5]
+            .into_iter()
+            .map(|angle: f32| pos + Vec2::from_angle(angle).rotate(away) * FLEE_DISTANCE)
+            .find(|&spot| game.navigation.is_walkable(spot.floor().as_ivec2()))
+    }
+}
+impl Component for EnemyAiComponent {
+    fn update<'a>(&mut self, entity: &mut Entity<'a>, game: &mut Game<'a>, dt: f32) {
+        let pos = entity.rect.pos;
+        let settings = self.settings;
+        self.cooldown = (self.cooldown - dt).max(0.0);
+
+        let seen = self.look(entity, game);
+        self.memory = match (seen, self.memory) {
+            (Some(seen), _) => Some(Memory {
+                pos: seen,
+                age: 0.0,
+            }),
+            (None, Some(memory)) if memory.age + dt <= settings.memory => Some(Memory {
+                age: memory.age + dt,
+                ..memory
+            }),
+            (None, _) => None,
+        };
+        let hp = entity
+            .get::<HealthComponent>()
+            .map_or(i32::MAX, |health| health.hp);
+        if hp <= settings.flee_hp && self.memory.is_some() {
+            self.state = AiState::Flee;
+        }
+
+        // where to walk this tick
+        let mut goal = None;
+        self.state = match self.state {
+            AiState::Idle | AiState::Patrol(_) | AiState::Alert { .. } if seen.is_some() => {
+                AiState::Chase
+            }
+            AiState::Idle => self.calm_state(),
+            // the route was taken away while patrolling
+            AiState::Patrol(_) if self.patrol.is_empty() => AiState::Idle,
+            AiState::Patrol(index) => {
+                let index = index % self.patrol.len();
+                let point = self.patrol[index];
+                if pos.distance(point) < ARRIVED {
+                    AiState::Patrol((index + 1) % self.patrol.len())
+                } else {
+                    goal = Some(point);
+                    AiState::Patrol(index)
+                }
+            }
+            AiState::Alert {
+                pos: spot,
+                time_left,
+            } => {
+                if pos.distance(spot) < ARRIVED {
+                    entity.look_angle += LOOK_AROUND_SPEED * dt;
+                } else {
+                    goal = Some(spot);
+                }
+                if time_left <= dt {
+                    self.calm_state()
+                } else {
+                    AiState::Alert {
+                        pos: spot,
+                        time_left: time_left - dt,
+                    }
+                }
+            }
+            AiState::Chase => match (seen, self.memory) {
+                (Some(target), _) if pos.distance(target) <= settings.attack_distance => {
+                    AiState::Attack
+                }
+                // lost sight and got to where the target was last seen
+                (None, Some(memory)) if pos.distance(memory.pos) < ARRIVED => AiState::Alert {
+                    pos: memory.pos,
+                    time_left: settings.alert_time,
+                },
+                (_, Some(memory)) => {
+                    goal = Some(memory.pos);
+                    AiState::Chase
+                }
+                (_, None) => self.calm_state(),
+            },
+            AiState::Attack => match seen {
+                Some(target) if pos.distance(target) <= settings.attack_distance => {
+                    let dir = (target - pos).normalize_or_zero();
+                    entity.look_angle = angle_of(dir);
+                    if self.cooldown == 0.0 {
+                        fire_projectile(entity, game, dir);
+                        self.cooldown = settings.fire_cooldown;
+                    }
+                    AiState::Attack
+                }
+                _ => AiState::Chase,
+            },
+            AiState::Flee => match self.memory {
+                Some(memory) => {
+                    goal = Self::flee_point(pos, memory.pos, game);
+                    AiState::Flee
+                }
+                None => self.calm_state(),
+            },
+        };
+
+        if goal.is_some() && entity.vel != Vec2::ZERO {
+            entity.look_angle = angle_of(entity.vel);
+        }
+        match entity.get_mut::<PathFollowComponent>() {
+            Some(follow) => follow.target = goal,
+            // starts walking next tick, once the component has been added
+            None if goal.is_some() => {
+                game.add_component(entity.id, Box::new(PathFollowComponent::new(WALK_SPEED)))
+            }
+            None => {}
+        }
+    }
+
+    fn on_event<'a>(&mut self, entity: &mut Entity<'a>, game: &mut Game<'a>, event: &Event) {
+        let calm = matches!(
+            self.state,
+            AiState::Idle | AiState::Patrol(_) | AiState::Alert { .. }
+        );
+        match *event {
+            Event::Fired {
+                entity: shooter,
+                pos,
+            } if calm
+                && entity.rect.pos.distance(pos) <= self.settings.hearing_distance
+                && Self::is_hostile(entity, game, shooter) =>
+            {
+                self.state = AiState::Alert {
+                    pos,
+                    time_left: self.settings.alert_time,
+                };
+            }
+            Event::Damaged { target, source, .. }
+                if target == entity.id && Self::is_hostile(entity, game, source) =>
+            {
+                if let Some(source) = game.entities.get(source) {
+                    self.memory = Some(Memory {
+                        pos: source.rect.pos,
+                        age: 0.0,
+                    });
+                    if calm {
+                        self.state = AiState::Chase;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The direction an entity with `look_angle` faces, the same way
+/// `PlayerInputComponent` turns.
+fn facing(look_angle: f32) -> Vec2 {
+    vec2(look_angle.sin(), -look_angle.cos())
+}
+fn angle_of(dir: Vec2) -> f32 {
+    dir.x.atan2(-dir.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::BasicCollisionComponent,
+        tile_map::test_map,
+        Audio, HeadlessPlatform, TICK,
+    };
+
+    /// A game with a `player` faction target at `target` and a monster at
+    /// `(1.5, 1.5)` facing down the map.
+    fn game(rows: &[&str], target: Vec2) -> (Game<'static>, u32) {
+        let mut game = Game::new(
+            Box::new(HeadlessPlatform::new(None)),
+            Audio::silent(),
+            test_map(rows),
+            16,
+            16,
+        );
+        game.add_entity(Entity::new(
+            target,
+            None,
+            Vec2::ZERO,
+            0.6,
+            true,
+            vec![
+                Box::new(FactionComponent { name: "player" }),
+                Box::new(HealthComponent { hp: 100 }),
+            ],
+        ));
+        let mut enemy = Entity::new(
+            vec2(1.5, 1.5),
+            None,
+            Vec2::ZERO,
+            0.6,
+            true,
+            vec![
+                Box::new(BasicCollisionComponent),
+                Box::new(EnemyAiComponent::new(AiSettings::default(), Vec::new())),
+                Box::new(PathFollowComponent::new(1.0)),
+                Box::new(FactionComponent { name: "monsters" }),
+                Box::new(HealthComponent { hp: 2 }),
+            ],
+        );
+        enemy.look_angle = angle_of(vec2(0.0, 1.0));
+        let id = game.entities.add(enemy);
+        (game, id)
+    }
+
+    fn state(game: &Game, id: u32) -> AiState {
+        game.entities
+            .get(id)
+            .unwrap()
+            .get::<EnemyAiComponent>()
+            .unwrap()
+            .state
+    }
+
+    fn ai<'g>(game: &'g mut Game, id: u32) -> &'g mut EnemyAiComponent {
+        game.entities
+            .get_mut(id)
+            .unwrap()
+            .get_mut::<EnemyAiComponent>()
+            .unwrap()
+    }
+
+    /// Ticks until `done` or `max_ticks` run out, returns whether `done` was
+    /// reached.
+    fn tick_until(game: &mut Game, max_ticks: usize, done: impl Fn(&Game) -> bool) -> bool {
+        (0..max_ticks).any(|_| {
+            game.tick(TICK);
+            done(game)
+        })
+    }
+
+    #[test]
+    fn chases_and_attacks_what_it_sees() {
+        let rows = [
+            "111",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "111",
+        ];
+        let (mut game, id) = game(&rows, vec2(1.5, 7.5));
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Chase);
+        for _ in 0..120 {
+            game.tick(TICK);
+        }
+        assert_eq!(state(&game, id), AiState::Attack);
+        assert!(game.entities.get(id).unwrap().rect.pos.y > 2.0);
+    }
+
+    #[test]
+    fn gets_a_path_follower_to_walk_with() {
+        let rows = [
+            "111",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "111",
+        ];
+        let (mut game, id) = game(&rows, vec2(1.5, 7.5));
+        game.entities.remove(id);
+        let mut enemy = Entity::new(
+            vec2(1.5, 1.5),
+            None,
+            Vec2::ZERO,
+            0.6,
+            true,
+            vec![
+                Box::new(BasicCollisionComponent),
+                Box::new(EnemyAiComponent::new(AiSettings::default(), Vec::new())),
+                Box::new(FactionComponent { name: "monsters" }),
+            ],
+        );
+        enemy.look_angle = angle_of(vec2(0.0, 1.0));
+        let id = game.entities.add(enemy);
+        assert!(tick_until(&mut game, 2, |game| {
+            game.entities.get(id).unwrap().has::<PathFollowComponent>()
+        }));
+        assert!(tick_until(&mut game, 120, |game| {
+            game.entities.get(id).unwrap().rect.pos.y > 2.0
+        }));
+    }
+
+    #[test]
+    fn does_not_see_behind_itself_or_through_walls() {
+        let rows = [
+            "11111",
+            "1   1",
+            "1 111",
+            "1   1",
+            "11111",
+        ];
+        let (mut game, id) = game(&rows, vec2(3.5, 3.5));
+        game.entities.get_mut(id).unwrap().look_angle = angle_of(vec2(0.0, -1.0));
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Idle);
+
+        game.entities.get_mut(id).unwrap().look_angle = angle_of(vec2(1.0, 1.0));
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Idle);
+    }
+
+    #[test]
+    fn patrols_its_route_in_a_loop() {
+        let rows = [
+            "11111",
+            "1   1",
+            "1   1",
+            "1   1",
+            "1   1",
+            "11111",
+            "1   1",
+            "11111",
+        ];
+        // the target is walled off and never seen
+        let (mut game, id) = game(&rows, vec2(2.5, 6.5));
+        ai(&mut game, id).patrol = vec![vec2(3.5, 4.5), vec2(1.5, 1.5)];
+        let pos = |game: &Game| game.entities.get(id).unwrap().rect.pos;
+
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Patrol(0));
+        assert!(tick_until(&mut game, 600, |game| state(game, id) == AiState::Patrol(1)));
+        assert!(pos(&game).distance(vec2(3.5, 4.5)) < ARRIVED);
+        assert!(tick_until(&mut game, 600, |game| state(game, id) == AiState::Patrol(0)));
+        assert!(pos(&game).distance(vec2(1.5, 1.5)) < ARRIVED);
+    }
+
+    #[test]
+    fn idles_when_patrolling_without_a_route() {
+        let rows = [
+            "11111",
+            "1   1",
+            "11111",
+            "1   1",
+            "11111",
+        ];
+        let (mut game, id) = game(&rows, vec2(2.5, 3.5));
+        ai(&mut game, id).state = AiState::Patrol(1);
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Idle);
+    }
+
+    #[test]
+    fn calms_down_once_it_forgets_the_target() {
+        let rows = [
+            "111",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "111",
+        ];
+        let (mut game, id) = game(&rows, vec2(1.5, 7.5));
+        ai(&mut game, id).settings.memory = 0.5;
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Chase);
+
+        // dead targets aren't looked for, the last sighting fades
+        game.entities
+            .get_mut(0)
+            .unwrap()
+            .get_mut::<HealthComponent>()
+            .unwrap()
+            .hp = 0;
+        for _ in 0..20 {
+            game.tick(TICK);
+        }
+        assert_eq!(state(&game, id), AiState::Chase);
+        assert!(tick_until(&mut game, 20, |game| state(game, id) == AiState::Idle));
+    }
+
+    #[test]
+    fn flees_once_hurt_down_to_flee_hp() {
+        let rows = [
+            "111",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "111",
+        ];
+        let (mut game, id) = game(&rows, vec2(1.5, 10.5));
+        ai(&mut game, id).settings.flee_hp = 1;
+        game.entities.get_mut(id).unwrap().rect.pos = vec2(1.5, 6.5);
+        game.emit(Event::Damaged {
+            target: id,
+            source: 0,
+            amount: 1,
+        });
+        game.tick(TICK);
+        game.tick(TICK);
+        assert_eq!(state(&game, id), AiState::Flee);
+        for _ in 0..120 {
+            game.tick(TICK);
+        }
+        assert_eq!(state(&game, id), AiState::Flee);
+        assert!(game.entities.get(id).unwrap().rect.pos.y < 5.5);
+    }
+
+    #[test]
+    fn hears_gunfire_and_searches_where_it_came_from() {
+        let rows = [
+            "11111",
+            "1   1",
+            "1 111",
+            "1   1",
+            "11111",
+        ];
+        let (mut game, id) = game(&rows, vec2(3.5, 3.5));
+        game.entities.get_mut(id).unwrap().look_angle = angle_of(vec2(0.0, -1.0));
+        game.emit(Event::Fired {
+            entity: 0,
+            pos: vec2(3.5, 3.5),
+        });
+        game.tick(TICK);
+        assert!(matches!(
+            state(&game, id),
+            AiState::Alert { pos, .. } if pos == vec2(3.5, 3.5)
+        ));
+    }
+}
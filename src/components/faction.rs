@@ -0,0 +1,18 @@
+use super::Component;
+use crate::{entity::Entity, Game};
+
+/// Which side an entity is on. `EnemyAiComponent`s go after entities of
+/// every other faction, entities without a faction are left alone.
+pub struct FactionComponent {
+    pub name: &'static str,
+}
+impl FactionComponent {
+    /// Whether an entity of this faction attacks `other`. Entities without a
+    /// faction attack every faction.
+    pub fn is_hostile(own: Option<&Self>, other: &Self) -> bool {
+        own.is_none_or(|own| own.name != other.name)
+    }
+}
+impl Component for FactionComponent {
+    fn update<'a>(&mut self, _entity: &mut Entity<'a>, _game: &mut Game<'a>, _dt: f32) {}
+}
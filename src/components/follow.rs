@@ -0,0 +1,61 @@
+use glam::*;
+
+use super::Component;
+use crate::{entity::Entity, Game};
+
+/// Walks towards `target` along a path found by `Game::navigation`, other
+/// components set the target. Stands still while the target can't be
+/// reached.
+pub struct PathFollowComponent {
+    pub target: Option<Vec2>,
+    pub speed: f32,
+    path: Option<Vec<Vec2>>,
+    /// Own cell, target cell and navigation version `path` was found for.
+    found_for: Option<(IVec2, IVec2, u32)>,
+}
+impl PathFollowComponent {
+    pub fn new(speed: f32) -> Self {
+        PathFollowComponent {
+            target: None,
+            speed,
+            path: None,
+            found_for: None,
+        }
+    }
+}
+impl Component for PathFollowComponent {
+    fn update<'a>(&mut self, entity: &mut Entity<'a>, game: &mut Game<'a>, _dt: f32) {
+        let Some(target) = self.target else {
+            entity.vel = Vec2::ZERO;
+            return;
+        };
+        let pos = entity.rect.pos;
+        let key = (
+            pos.floor().as_ivec2(),
+            target.floor().as_ivec2(),
+            game.navigation.version,
+        );
+        if self.found_for != Some(key) {
+            self.found_for = Some(key);
+            self.path = game.navigation.find_path(pos, target);
+        }
+        let Some(path) = &mut self.path else {
+            entity.vel = Vec2::ZERO;
+            return;
+        };
+
+        while path
+            .first()
+            .is_some_and(|waypoint| waypoint.distance(pos) < 0.1)
+        {
+            path.remove(0);
+        }
+        // the last stretch goes straight to the target
+        let to_next = path.first().copied().unwrap_or(target) - pos;
+        entity.vel = if to_next.length() < 0.05 {
+            Vec2::ZERO
+        } else {
+            to_next.normalize() * self.speed
+        };
+    }
+}
@@ -0,0 +1,21 @@
+use super::Component;
+use crate::{entity::Entity, events::Event, Game};
+
+/// Hit points, taken away by `Damaged` events. Emits `Died` when they run
+/// out, see `DeathComponent` for what happens then.
+pub struct HealthComponent {
+    pub hp: i32,
+}
+impl Component for HealthComponent {
+    fn update<'a>(&mut self, _entity: &mut Entity<'a>, _game: &mut Game<'a>, _dt: f32) {}
+    fn on_event<'a>(&mut self, entity: &mut Entity<'a>, game: &mut Game<'a>, event: &Event) {
+        if let Event::Damaged { target, amount, .. } = *event {
+            if target == entity.id && self.hp > 0 {
+                self.hp -= amount;
+                if self.hp <= 0 {
+                    game.emit(Event::Died { entity: entity.id });
+                }
+            }
+        }
+    }
+}
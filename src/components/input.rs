@@ -1,8 +1,15 @@
-use minifb::Key;
-
-use crate::{entity::Entity, Game};
+use crate::{
+    controls::Action,
+    entity::Entity,
+    events::Event,
+    raycast::{RayHit, RayTarget},
+    Game,
+};
 use glam::Vec2;
-use super::{Component, ProjectileCollisionComponent};
+use super::{fire_projectile, Component, MAX_PITCH};
+
+/// How far away the player can open doors, from their centre.
+const USE_RANGE: f32 = 1.5;
 
 pub struct PlayerInputComponent;
 impl Component  for PlayerInputComponent {
@@ -11,43 +18,49 @@ impl Component  for PlayerInputComponent {
         let player = entity;
         let p_speed = 3.0;
         let dir_vec = Vec2::from_angle(player.look_angle);
-        game.window.get_keys().iter().for_each(|key| match key {
-            Key::A => vel += Vec2::new(-p_speed, 0.0).rotate(dir_vec),
-            Key::D => vel += Vec2::new(p_speed, 0.0).rotate(dir_vec),
-            Key::W => vel += Vec2::new(0.0, -p_speed).rotate(dir_vec),
-            Key::S => vel += Vec2::new(0.0, p_speed).rotate(dir_vec),
-            Key::Left => player.look_angle -= 2.0 * dt,
-            Key::Right => player.look_angle += 2.0 * dt,
-
-            _ => (),
-        });
+        let actions = &game.actions;
+        if actions.is_down(Action::StrafeLeft) {
+            vel += Vec2::new(-p_speed, 0.0).rotate(dir_vec);
+        }
+        if actions.is_down(Action::StrafeRight) {
+            vel += Vec2::new(p_speed, 0.0).rotate(dir_vec);
+        }
+        if actions.is_down(Action::MoveForward) {
+            vel += Vec2::new(0.0, -p_speed).rotate(dir_vec);
+        }
+        if actions.is_down(Action::MoveBack) {
+            vel += Vec2::new(0.0, p_speed).rotate(dir_vec);
+        }
+        if actions.is_down(Action::TurnLeft) {
+            player.look_angle -= 2.0 * dt;
+        }
+        if actions.is_down(Action::TurnRight) {
+            player.look_angle += 2.0 * dt;
+        }
+        let mut pitch = player.look_pitch + actions.look.y;
+        if actions.is_down(Action::LookUp) {
+            pitch += dt;
+        }
+        if actions.is_down(Action::LookDown) {
+            pitch -= dt;
+        }
+        player.look_pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        player.look_angle += actions.look.x;
 
         player.vel = vel;
         let dir = Vec2::new(0.0, -1.0).rotate(dir_vec);
-        game.window
-            .get_keys_pressed(minifb::KeyRepeat::No)
-            .iter()
-            .for_each(|key| match key {
-                Key::Space => {
-                    
-
-                    game.add_entity(Entity::new(
-                        player.rect.pos + dir * 0.5,
-                        Some("assets/explosion/explosion1.png"),
-                        dir * 8.0,
-                        0.3,
-                        false,
-                        vec![Box::new(ProjectileCollisionComponent::new(player.id))],
-                    ));
-                    let sound_data = game.assets.load_sound("assets/sounds/laserRetro_002.ogg", None);
-                    game.audio_manager.play(sound_data.clone()).unwrap();
-                }
-                Key::E => {
-                    let pos = player.rect.pos + dir;
-                    let pos = pos.as_ivec2();
-                    game.tile_map.tile_update_indeces.push(pos.x as usize + pos.y as usize * game.tile_map.width);
-                }
-                _ => (),
-            })
+        if actions.is_pressed(Action::Fire) {
+            fire_projectile(player, game, dir);
+        }
+        if game.actions.is_pressed(Action::Use) {
+            let hit = game.cast_ray(player.rect.pos, dir, USE_RANGE, Some(player.id));
+            if let Some(RayHit { target: RayTarget::Tile(pos), .. }) = hit {
+                game.tile_map.tile_update_indeces.push(pos.x as usize + pos.y as usize * game.tile_map.width);
+                game.emit(Event::Used {
+                    entity: player.id,
+                    tile: pos,
+                });
+            }
+        }
     }
 }
@@ -1,11 +1,35 @@
-use crate::{entity::Entity, Game};
+use std::any::Any;
 
-pub trait Component {
+use crate::{entity::Entity, events::Event, Game};
+
+/// Lets `Entity::get` find components by type.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait Component: AsAny {
+    /// Called once per simulation tick.
     fn update<'a>(&mut self, entity: &mut Entity<'a>, game: &mut Game<'a>, dt: f32);
+    /// Called once per rendered frame, with the entity at its interpolated
+    /// position.
+    fn render<'a>(&mut self, _entity: &Entity<'a>, _game: &mut Game<'a>) {}
+    /// Called at the end of every tick for each event emitted during it.
+    fn on_event<'a>(&mut self, _entity: &mut Entity<'a>, _game: &mut Game<'a>, _event: &Event) {}
 }
 
 pub mod collision;
 pub use collision::*;
+pub mod weapon;
+pub use weapon::*;
 pub mod camera;
 pub use camera::*;
 pub mod input;
@@ -16,3 +40,11 @@ pub mod anim;
 pub use anim::*;
 pub mod death;
 pub use death::*;
+pub mod health;
+pub use health::*;
+pub mod follow;
+pub use follow::*;
+pub mod faction;
+pub use faction::*;
+pub mod enemy;
+pub use enemy::*;
@@ -0,0 +1,28 @@
+use glam::{vec3, Vec2};
+
+use super::ProjectileCollisionComponent;
+use crate::{entity::Entity, events::Event, lighting::LightSource, Game};
+
+/// Shoots a projectile from `shooter` along `dir` and lets everyone in
+/// earshot know with a `Fired` event.
+pub fn fire_projectile(shooter: &Entity, game: &mut Game, dir: Vec2) {
+    let mut projectile = Entity::new(
+        shooter.rect.pos + dir * 0.5,
+        Some("assets/explosion/explosion1.png"),
+        dir * 8.0,
+        0.3,
+        false,
+        vec![Box::new(ProjectileCollisionComponent::new(shooter.id))],
+    );
+    projectile.light = Some(LightSource {
+        color: vec3(0.8, 0.5, 0.2),
+        radius: 2.5,
+    });
+    game.spawn(projectile);
+    game.emit(Event::Fired {
+        entity: shooter.id,
+        pos: shooter.rect.pos,
+    });
+    let sound_data = game.assets.load_sound("assets/sounds/laserRetro_002.ogg", None);
+    game.audio.play(sound_data.clone());
+}
@@ -0,0 +1,272 @@
+use std::{collections::HashMap, error::Error, fmt, fs, io};
+
+use glam::*;
+use minifb::{Key, MouseButton};
+use serde::{Deserialize, Serialize};
+
+use crate::platform::Platform;
+
+/// Everything the player can do. Gameplay code asks for actions instead of
+/// keys, so controls can be rebound in `assets/controls.ron`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    StrafeLeft,
+    StrafeRight,
+    TurnLeft,
+    TurnRight,
+    LookUp,
+    LookDown,
+    Fire,
+    Use,
+    /// Captures or releases the mouse.
+    ToggleMouse,
+    Screenshot,
+    Record,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+}
+
+/// How a binding is written in the controls file, `Key("W")` or
+/// `Mouse("Left")`. Names are the `minifb` names.
+#[derive(Deserialize)]
+pub enum BindingDef {
+    Key(String),
+    Mouse(String),
+}
+impl BindingDef {
+    pub fn to_binding(&self) -> Result<Binding, ControlsError> {
+        let binding = match self {
+            BindingDef::Key(name) => key_from_name(name).map(Binding::Key),
+            BindingDef::Mouse(name) => match name.as_str() {
+                "Left" => Some(Binding::Mouse(MouseButton::Left)),
+                "Middle" => Some(Binding::Mouse(MouseButton::Middle)),
+                "Right" => Some(Binding::Mouse(MouseButton::Right)),
+                _ => None,
+            },
+        };
+        binding.ok_or_else(|| match self {
+            BindingDef::Key(name) | BindingDef::Mouse(name) => {
+                ControlsError::UnknownBinding(name.clone())
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct MouseSettings {
+    /// Radians turned per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Moving the mouse up looks down.
+    pub invert_y: bool,
+}
+impl Default for MouseSettings {
+    fn default() -> Self {
+        MouseSettings {
+            sensitivity: 0.003,
+            invert_y: false,
+        }
+    }
+}
+
+/// The controls file, see `assets/controls.ron`.
+#[derive(Deserialize)]
+struct ControlsFile {
+    #[serde(default)]
+    mouse: MouseSettings,
+    /// Replaces the default bindings of every action it lists.
+    #[serde(default)]
+    bindings: HashMap<Action, Vec<BindingDef>>,
+}
+
+#[derive(Debug)]
+pub enum ControlsError {
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    Parse {
+        path: String,
+        source: ron::error::SpannedError,
+    },
+    UnknownBinding(String),
+}
+impl fmt::Display for ControlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlsError::Io { path, source } => write!(f, "couldn't read {path}: {source}"),
+            ControlsError::Parse { path, source } => write!(f, "couldn't parse {path}: {source}"),
+            ControlsError::UnknownBinding(name) => write!(f, "unknown key or button {name:?}"),
+        }
+    }
+}
+impl Error for ControlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ControlsError::Io { source, .. } => Some(source),
+            ControlsError::Parse { source, .. } => Some(source),
+            ControlsError::UnknownBinding(_) => None,
+        }
+    }
+}
+
+/// Maps keys and mouse buttons to actions. An action can have any number of
+/// bindings and is down while any of them is.
+pub struct Controls {
+    bindings: HashMap<Action, Vec<Binding>>,
+    pub mouse: MouseSettings,
+}
+impl Default for Controls {
+    fn default() -> Self {
+        let key = Binding::Key;
+        Controls {
+            bindings: HashMap::from([
+                (Action::MoveForward, vec![key(Key::W)]),
+                (Action::MoveBack, vec![key(Key::S)]),
+                (Action::StrafeLeft, vec![key(Key::A)]),
+                (Action::StrafeRight, vec![key(Key::D)]),
+                (Action::TurnLeft, vec![key(Key::Left)]),
+                (Action::TurnRight, vec![key(Key::Right)]),
+                (Action::LookUp, vec![key(Key::Up)]),
+                (Action::LookDown, vec![key(Key::Down)]),
+                (Action::Fire, vec![key(Key::Space), Binding::Mouse(MouseButton::Left)]),
+                (Action::Use, vec![key(Key::E)]),
+                (Action::ToggleMouse, vec![key(Key::Tab)]),
+                (Action::Screenshot, vec![key(Key::F12)]),
+                (Action::Record, vec![key(Key::F11)]),
+                (Action::Quit, vec![key(Key::Escape)]),
+            ]),
+            mouse: MouseSettings::default(),
+        }
+    }
+}
+impl Controls {
+    /// Loads a controls file on top of the default controls.
+    pub fn load(path: &str) -> Result<Self, ControlsError> {
+        let contents = fs::read_to_string(path).map_err(|source| ControlsError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        let file: ControlsFile =
+            ron::from_str(&contents).map_err(|source| ControlsError::Parse {
+                path: path.to_string(),
+                source,
+            })?;
+
+        let mut controls = Controls {
+            mouse: file.mouse,
+            ..Controls::default()
+        };
+        for (action, defs) in file.bindings {
+            let bindings = defs
+                .iter()
+                .map(BindingDef::to_binding)
+                .collect::<Result<_, _>>()?;
+            controls.bindings.insert(action, bindings);
+        }
+        Ok(controls)
+    }
+
+    /// Reads this frame's actions from `platform`. Also handles capturing
+    /// the mouse: `ToggleMouse` captures or releases it and a click into the
+    /// window captures it without counting as an action.
+    pub fn poll(&self, platform: &mut dyn Platform) -> Actions {
+        let keys = platform.get_keys();
+        let keys_pressed = platform.get_keys_pressed();
+        let mut mouse_down = platform.get_mouse_down();
+        let mut mouse_pressed = platform.get_mouse_pressed();
+        let captured = platform.is_mouse_captured();
+        if !captured && mouse_pressed.contains(&MouseButton::Left) {
+            platform.set_mouse_captured(true);
+            mouse_down.clear();
+            mouse_pressed.clear();
+        }
+
+        let mut actions = Actions::default();
+        for (&action, bindings) in &self.bindings {
+            let matches = |keys: &[Key], buttons: &[MouseButton]| {
+                bindings.iter().any(|binding| match binding {
+                    Binding::Key(key) => keys.contains(key),
+                    Binding::Mouse(button) => buttons.contains(button),
+                })
+            };
+            if matches(&keys, &mouse_down) {
+                actions.down.push(action);
+            }
+            if matches(&keys_pressed, &mouse_pressed) {
+                actions.pressed.push(action);
+            }
+        }
+        if actions.is_pressed(Action::ToggleMouse) {
+            platform.set_mouse_captured(!captured);
+        }
+        if captured {
+            let delta = platform.get_mouse_delta() * self.mouse.sensitivity;
+            actions.look = vec2(delta.x, if self.mouse.invert_y { delta.y } else { -delta.y });
+        }
+        actions
+    }
+}
+
+/// The actions of a single frame or simulation tick.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Actions {
+    down: Vec<Action>,
+    pressed: Vec<Action>,
+    /// Mouse look in radians, `x` turns right and `y` looks up.
+    pub look: Vec2,
+}
+impl Actions {
+    /// Whether any binding of `action` is held.
+    pub fn is_down(&self, action: Action) -> bool {
+        self.down.contains(&action)
+    }
+    /// Whether a binding of `action` went down this frame.
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+    /// Adds the actions of a newer frame, presses and mouse look pile up
+    /// until they are taken by a tick.
+    pub fn merge(&mut self, newer: &Actions) {
+        self.down.clone_from(&newer.down);
+        for &action in &newer.pressed {
+            if !self.pressed.contains(&action) {
+                self.pressed.push(action);
+            }
+        }
+        self.look += newer.look;
+    }
+    /// Takes the actions for a tick, leaving only what is still held for the
+    /// next one.
+    pub fn take(&mut self) -> Actions {
+        Actions {
+            down: self.down.clone(),
+            pressed: std::mem::take(&mut self.pressed),
+            look: std::mem::take(&mut self.look),
+        }
+    }
+}
+
+fn key_from_name(name: &str) -> Option<Key> {
+    use Key::*;
+    [
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, A, B, C, D, E, F, G, H, I, J,
+        K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10,
+        F11, F12, F13, F14, F15, Down, Left, Right, Up, Apostrophe, Backquote, Backslash, Comma,
+        Equal, LeftBracket, Minus, Period, RightBracket, Semicolon, Slash, Backspace, Delete, End,
+        Enter, Escape, Home, Insert, Menu, PageDown, PageUp, Pause, Space, Tab, NumLock, CapsLock,
+        ScrollLock, LeftShift, RightShift, LeftCtrl, RightCtrl, NumPad0, NumPad1, NumPad2,
+        NumPad3, NumPad4, NumPad5, NumPad6, NumPad7, NumPad8, NumPad9, NumPadDot, NumPadSlash,
+        NumPadAsterisk, NumPadMinus, NumPadPlus, NumPadEnter, LeftAlt, RightAlt, LeftSuper,
+        RightSuper,
+    ]
+    .into_iter()
+    .find(|key| format!("{key:?}") == name)
+}
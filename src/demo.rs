@@ -0,0 +1,93 @@
+use std::{error::Error, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::controls::Actions;
+
+/// A recorded session: the level and the actions and `dt` of every
+/// simulation tick. Playing it back reproduces the session exactly.
+#[derive(Serialize, Deserialize)]
+pub struct Demo {
+    pub level: String,
+    pub frames: Vec<DemoFrame>,
+}
+#[derive(Serialize, Deserialize)]
+pub struct DemoFrame {
+    pub dt: f32,
+    pub actions: Actions,
+}
+
+impl Demo {
+    pub fn new(level: &str) -> Self {
+        Demo {
+            level: level.to_string(),
+            frames: Vec::new(),
+        }
+    }
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::*;
+
+    use super::*;
+    use crate::{level::load_level, prefab::Prefabs, Game};
+
+    /// Plays `demo` back in a fresh headless game and returns the id,
+    /// position, velocity and look angle of every entity left at the end.
+    fn play(demo: &Demo) -> Vec<(u32, Vec2, Vec2, f32)> {
+        let level = load_level(&demo.level).unwrap();
+        let mut game = Game::headless(level.tile_map, None, Vec::new());
+        game.prefabs = Prefabs::load("assets/prefabs.ron").unwrap();
+        game.add_level_entities(
+            level.player_start,
+            level.look_angle,
+            &level.entities,
+            &level.prefabs,
+        )
+        .unwrap();
+        for frame in &demo.frames {
+            game.actions = frame.actions.clone();
+            game.tick(frame.dt);
+        }
+        game.entities
+            .iter()
+            .map(|entity| (entity.id, entity.rect.pos, entity.vel, entity.look_angle))
+            .collect()
+    }
+
+    #[test]
+    fn plays_back_the_same_twice() {
+        // walk and turn through the level firing every half second
+        let frames: Vec<String> = (0..600)
+            .map(|tick| {
+                let pressed = if tick % 30 == 0 { "Fire" } else { "" };
+                let look = if tick < 300 { 0.01 } else { -0.005 };
+                format!(
+                    "(dt: 0.016666668, actions: (down: [MoveForward, StrafeLeft], \
+                     pressed: [{pressed}], look: ({look}, 0.0)))"
+                )
+            })
+            .collect();
+        let demo: Demo = ron::from_str(&format!(
+            "(level: \"assets/levels/level1.ron\", frames: [{}])",
+            frames.join(", ")
+        ))
+        .unwrap();
+
+        let first = play(&demo);
+        assert_eq!(first, play(&demo));
+        let player = first.iter().find(|&&(id, ..)| id == 0).unwrap();
+        assert_ne!(player.1, play(&Demo::new(&demo.level))[0].1);
+    }
+}
@@ -1,13 +1,9 @@
-use crate::{
-    drawing::{draw_rect, val_from_rgb},
-    math::set_value_brightness,
-    surface::Surface,
-    AssetCache,
-};
+use crate::{fog::Fog, surface::Surface, AssetCache};
 use glam::*;
+use serde::Deserialize;
 use std::{cmp::Ordering, collections::BinaryHeap};
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Deserialize)]
 
 pub enum Direction {
     Horizontal,
@@ -17,6 +13,8 @@ pub enum Direction {
 pub struct DepthBufferData<'a> {
     pub distance: f32,
     pub column: i32,
+    /// Light falling on the wall or sprite, on top of full brightness.
+    pub light: Vec3,
     pub data_type: BufferDataType<'a>,
 }
 
@@ -42,6 +40,9 @@ pub enum BufferDataType<'a> {
         direction: Direction,
         percentage: f32,
         sprite: &'a str,
+        /// Height of the bottom edge above the floor, in tiles.
+        elevation: f32,
+        height: f32,
     },
     Sprite {
         surf: &'a str,
@@ -50,50 +51,74 @@ pub enum BufferDataType<'a> {
 
 pub struct DepthBufferRenderer<'a> {
     pub data: BinaryHeap<DepthBufferData<'a>>,
+    /// Distance to the nearest opaque wall in every screen column. Sprite
+    /// columns behind it are skipped.
+    pub z_buffer: Vec<f32>,
+    pub fog: Fog,
+    /// Screen row the eye level is drawn at, moves with the camera pitch.
+    pub horizon: i32,
 }
 impl DepthBufferRenderer<'_> {
-    pub fn new(capacity: usize) -> Self {
+    pub fn new(capacity: usize, fog: Fog) -> Self {
         DepthBufferRenderer {
             data: BinaryHeap::with_capacity(capacity),
+            z_buffer: Vec::with_capacity(capacity),
+            fog,
+            horizon: 0,
         }
     }
+    pub fn clear_z_buffer(&mut self, width: usize) {
+        self.z_buffer.clear();
+        self.z_buffer.resize(width, f32::INFINITY);
+    }
     pub fn render(&mut self, screen: &mut Surface, sprites: &mut AssetCache) {
         for _ in 0..self.data.len() {
             let buf_data: DepthBufferData<'_> = self.data.pop().unwrap();
             let value = 1.0 / buf_data.distance;
-
-            let brightness = ((value.sqrt() + 0.2).min(1.0) * 255.0) as u32;
+            let fog = self.fog;
+            let light = Vec3::ONE + buf_data.light;
             match buf_data.data_type {
                 BufferDataType::Wall {
                     direction: _,
                     percentage,
                     sprite,
+                    elevation,
+                    height,
                 } => {
                     let wall_tex = sprites.load_png(sprite);
 
-                    let height = (value * 1.0 * screen.height as f32) as i32;
-                    let scale = height as f32 / wall_tex.height as f32;
+                    // pixels per tile at this distance, the texture repeats
+                    // once per tile from the top of the wall down
+                    let unit = (value * 1.0 * screen.height as f32) as i32;
+                    let scale = unit as f32 / wall_tex.height as f32;
+                    let height_px = (unit as f32 * height) as i32;
 
-                    let offset = screen.height as i32 / 2 - height / 2;
-                    let wall_x = (wall_tex.width as f32 * percentage) as usize;
+                    // the eye is half a tile above the floor
+                    let offset = self.horizon
+                        - ((elevation + height - 0.5) * unit as f32).floor() as i32;
+                    let wall_x =
+                        ((wall_tex.width as f32 * percentage) as usize).min(wall_tex.width - 1);
                     let x = buf_data.column as u32;
-                    for y in 0..height {
-                        let col = wall_tex.pixel_buffer
-                            [wall_x + (y as f32 / scale) as usize * wall_tex.width];
+                    for y in (-offset).max(0)..height_px.min(screen.height as i32 - offset) {
+                        let tex_y = (y as f32 / scale) as usize % wall_tex.height;
+                        let col = wall_tex.pixel_buffer[wall_x + tex_y * wall_tex.width];
                         if col != 0 {
                             let _ = screen.set_pixel(
                                 x,
                                 (y + offset) as u32,
-                                set_value_brightness(col, brightness),
+                                fog.shade(col, light, buf_data.distance),
                             );
                         }
                     }
                 }
                 BufferDataType::Sprite { surf } => {
-                    screen.blit_scaled(
+                    let z_buffer = &self.z_buffer;
+                    screen.blit_scaled_columns(
                         sprites.load_png(surf).as_ref(),
-                        IVec2::new(buf_data.column, screen.height as i32 / 2),
+                        IVec2::new(buf_data.column, self.horizon),
                         1.0 / buf_data.distance * 32.0,
+                        |x| z_buffer.get(x).is_none_or(|&z| buf_data.distance < z),
+                        |col| fog.shade_sprite(col, light, buf_data.distance),
                     );
                 }
             }
@@ -80,7 +80,15 @@ pub fn val_from_rgb(r: u32, g: u32, b: u32) -> u32 {
     b.min(255) | (g.min(255) << 8) | (r.min(255) << 16) | (255 << 24)
 }
 pub fn val_from_rgba(r: u8, g: u8, b: u8, a: u8) -> u32 {
-    (b as u32) | ((g as u32) << 8) | ((r as u32) << 16) as u32 | (a as u32) << 24
+    (b as u32) | ((g as u32) << 8) | ((r as u32) << 16) | (a as u32) << 24
+}
+pub fn rgba_from_val(val: u32) -> [u8; 4] {
+    [
+        (val >> 16 & 0xFF) as u8,
+        (val >> 8 & 0xFF) as u8,
+        (val & 0xFF) as u8,
+        (val >> 24) as u8,
+    ]
 }
 pub fn draw_dotted_line(surf: &mut Surface, p0: IVec2, p1: IVec2, value: u32) {
     for (x, y) in LineDrawer::new(p0.x, p0.y, p1.x, p1.y)
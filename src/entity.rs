@@ -1,18 +1,25 @@
+use std::collections::BTreeMap;
+
 use glam::*;
 
-use crate::{rect::Rect, Component, Game};
+use crate::{events::Event, lighting::LightSource, rect::Rect, Component, Game};
 
 pub struct Entity<'a> {
     pub sprite: Option<&'a str>,
     pub look_angle: f32,
+    /// Positive looks up.
+    pub look_pitch: f32,
     pub vel: Vec2,
 
     pub rect: Rect,
+    /// Position and look angle before the last tick, rendering interpolates
+    /// from them.
+    pub prev_pos: Vec2,
+    pub prev_look_angle: f32,
     pub collidable: bool,
-    components: Option<Vec<Box<dyn Component>>>,
-    pub alive: bool,
+    components: Vec<Box<dyn Component>>,
     pub id: u32,
-    pub health: i32,
+    pub light: Option<LightSource>,
 }
 impl<'a> Entity<'a> {
     pub fn new(
@@ -26,27 +33,200 @@ impl<'a> Entity<'a> {
         Entity {
             sprite,
             look_angle: 0.0,
+            look_pitch: 0.0,
             vel,
             rect: Rect {
                 pos,
                 width: size,
                 height: size,
             },
+            prev_pos: pos,
+            prev_look_angle: 0.0,
             collidable,
-            components: Some(components),
-            alive: true,
+            components,
             id: 0,
-            health: 2,
+            light: None,
         }
     }
 
-    pub fn update(&mut self, dt: f32, game: &mut Game<'a>) {
-        let components = self.components.take();
-        if let Some(mut components) = components {
-            for component in components.iter_mut() {
-                component.update(self, game, dt);
-            }
-            self.components = Some(components);
+    /// A copy without components, standing in for the entity in
+    /// `Game::entities` while it updates. Queries for its components come up
+    /// empty until it is put back.
+    fn snapshot(&self) -> Self {
+        Entity {
+            sprite: self.sprite,
+            look_angle: self.look_angle,
+            look_pitch: self.look_pitch,
+            vel: self.vel,
+            rect: self.rect,
+            prev_pos: self.prev_pos,
+            prev_look_angle: self.prev_look_angle,
+            collidable: self.collidable,
+            components: Vec::new(),
+            id: self.id,
+            light: self.light,
         }
     }
+
+    pub fn add_component(&mut self, component: Box<dyn Component>) {
+        self.components.push(component);
+    }
+
+    /// The first component of type `T`.
+    pub fn get<T: Component + 'static>(&self) -> Option<&T> {
+        self.components
+            .iter()
+            .find_map(|component| component.as_any().downcast_ref())
+    }
+    pub fn get_mut<T: Component + 'static>(&mut self) -> Option<&mut T> {
+        self.components
+            .iter_mut()
+            .find_map(|component| component.as_any_mut().downcast_mut())
+    }
+    pub fn has<T: Component + 'static>(&self) -> bool {
+        self.get::<T>().is_some()
+    }
+
+    /// Runs `run` on every component in turn. The running component is
+    /// swapped out for a placeholder, so it can look up the entity's other
+    /// components but not itself.
+    fn each_component(
+        &mut self,
+        game: &mut Game<'a>,
+        mut run: impl FnMut(&mut dyn Component, &mut Self, &mut Game<'a>),
+    ) {
+        for i in 0..self.components.len() {
+            let mut component = std::mem::replace(&mut self.components[i], Box::new(Placeholder));
+            run(component.as_mut(), self, game);
+            self.components[i] = component;
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, game: &mut Game<'a>) {
+        self.each_component(game, |component, entity, game| {
+            component.update(entity, game, dt)
+        });
+    }
+
+    pub fn on_event(&mut self, event: &Event, game: &mut Game<'a>) {
+        self.each_component(game, |component, entity, game| {
+            component.on_event(entity, game, event)
+        });
+    }
+
+    pub fn render(&mut self, game: &mut Game<'a>) {
+        self.each_component(game, |component, entity, game| {
+            component.render(entity, game)
+        });
+    }
+}
+
+struct Placeholder;
+impl Component for Placeholder {
+    fn update<'a>(&mut self, _entity: &mut Entity<'a>, _game: &mut Game<'a>, _dt: f32) {}
+}
+
+/// Every entity in the game by id. Ids are handed out in increasing order
+/// and iteration is always in id order, which is also the update order, so
+/// updates, rendering and anything written out come out the same every run.
+#[derive(Default)]
+pub struct Entities<'a> {
+    map: BTreeMap<u32, Entity<'a>>,
+    next_id: u32,
+}
+impl<'a> Entities<'a> {
+    /// Gives `entity` the next id and adds it.
+    pub fn add(&mut self, mut entity: Entity<'a>) -> u32 {
+        entity.id = self.reserve_id();
+        self.insert(entity)
+    }
+    /// Hands out an id for an entity that is added later with `insert`.
+    pub fn reserve_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+    /// Adds an entity under the id it already has.
+    pub fn insert(&mut self, mut entity: Entity<'a>) -> u32 {
+        // nothing to interpolate from before the first tick
+        entity.prev_pos = entity.rect.pos;
+        entity.prev_look_angle = entity.look_angle;
+        let id = entity.id;
+        self.map.insert(id, entity);
+        id
+    }
+    /// The entity `id`. While it is updating or handling an event this is
+    /// its snapshot, so `Entity::get` on it finds none of its components.
+    pub fn get(&self, id: u32) -> Option<&Entity<'a>> {
+        self.map.get(&id)
+    }
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Entity<'a>> {
+        self.map.get_mut(&id)
+    }
+    /// The ids of all entities, in update order.
+    pub fn ids(&self) -> Vec<u32> {
+        self.map.keys().copied().collect()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Entity<'a>> {
+        self.map.values()
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Entity<'a>> {
+        self.map.values_mut()
+    }
+    /// Takes an entity out so it can be updated with a mutable `Game`, hand
+    /// it back with `put_back`.
+    pub fn take(&mut self, id: u32) -> Option<Entity<'a>> {
+        self.map.remove(&id)
+    }
+    /// Like `take`, but leaves a copy without components behind so other
+    /// entities still see it.
+    pub fn take_leaving_snapshot(&mut self, id: u32) -> Option<Entity<'a>> {
+        let entity = self.map.remove(&id)?;
+        self.map.insert(id, entity.snapshot());
+        Some(entity)
+    }
+    pub fn remove(&mut self, id: u32) {
+        self.map.remove(&id);
+    }
+    /// Every entity with a `T` component, in id order. Leaves out the entity
+    /// that is updating or handling an event, its snapshot has no
+    /// components. It has to check its own components through the `Entity`
+    /// it was handed.
+    pub fn with<T: Component + 'static>(&self) -> impl Iterator<Item = (&Entity<'a>, &T)> {
+        self.iter()
+            .filter_map(|entity| entity.get::<T>().map(|component| (entity, component)))
+    }
+    /// Every entity with both an `A` and a `B` component, see `with`.
+    pub fn with2<A: Component + 'static, B: Component + 'static>(
+        &self,
+    ) -> impl Iterator<Item = (&Entity<'a>, &A, &B)> {
+        self.with::<A>()
+            .filter_map(|(entity, a)| entity.get::<B>().map(|b| (entity, a, b)))
+    }
+    pub fn put_back(&mut self, entity: Entity<'a>) {
+        self.map.insert(entity.id, entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Entities;
+    use crate::{
+        components::{FactionComponent, HealthComponent},
+        tile_map::test_entity,
+    };
+
+    #[test]
+    fn queries_entities_with_both_components() {
+        let mut entities = Entities::default();
+        let faction = || Box::new(FactionComponent { name: "player" });
+        let health = || Box::new(HealthComponent { hp: 2 });
+        entities.add(test_entity(vec![faction()]));
+        let both = entities.add(test_entity(vec![health(), faction()]));
+        entities.add(test_entity(vec![health()]));
+        let found: Vec<_> = entities
+            .with2::<FactionComponent, HealthComponent>()
+            .map(|(entity, faction, health)| (entity.id, faction.name, health.hp))
+            .collect();
+        assert_eq!(found, [(both, "player", 2)]);
+    }
 }
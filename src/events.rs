@@ -0,0 +1,140 @@
+use glam::*;
+
+/// Something that happened to an entity. Events are collected during a tick
+/// and delivered to every component through `Component::on_event` at the end
+/// of it, see `Game::tick`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// `target` lost `amount` hit points to `source`.
+    Damaged {
+        target: u32,
+        source: u32,
+        amount: i32,
+    },
+    /// `entity` ran out of hit points.
+    Died { entity: u32 },
+    /// `entity` ran into `other`, or into a wall when `other` is `None`.
+    Collided { entity: u32, other: Option<u32> },
+    /// `entity` fired a shot from `pos`, loud enough for `EnemyAiComponent`s
+    /// to hear.
+    Fired { entity: u32, pos: Vec2 },
+    /// `entity` used the tile at `tile`, opening it if it is a door.
+    Used { entity: u32, tile: IVec2 },
+    /// `entity` moved into the cell `tile`.
+    EnteredTile { entity: u32, tile: IVec2 },
+}
+
+/// Events emitted during the current tick, in the order they were emitted.
+#[derive(Default)]
+pub struct Events {
+    queue: Vec<Event>,
+}
+impl Events {
+    pub fn emit(&mut self, event: Event) {
+        self.queue.push(event);
+    }
+    /// Takes the events to deliver, anything emitted while delivering them
+    /// is delivered next tick.
+    pub fn take(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.queue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        components::{Component, DeathComponent, HealthComponent},
+        entity::Entity,
+        tile_map::{test_entity, test_game},
+        Game, TICK,
+    };
+
+    /// Writes down every event its entity gets, with the entity's id.
+    struct Recorder(Rc<RefCell<Vec<(u32, Event)>>>);
+    impl Component for Recorder {
+        fn update<'a>(&mut self, _entity: &mut Entity<'a>, _game: &mut Game<'a>, _dt: f32) {}
+        fn on_event<'a>(&mut self, entity: &mut Entity<'a>, _game: &mut Game<'a>, event: &Event) {
+            self.0.borrow_mut().push((entity.id, *event));
+        }
+    }
+
+    #[test]
+    fn delivers_events_in_emit_order_to_every_entity() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut game = test_game();
+        let first = game
+            .entities
+            .add(test_entity(vec![Box::new(Recorder(Rc::clone(&log)))]));
+        let second = game
+            .entities
+            .add(test_entity(vec![Box::new(Recorder(Rc::clone(&log)))]));
+        let died = Event::Died { entity: second };
+        let used = Event::Used {
+            entity: first,
+            tile: IVec2::ZERO,
+        };
+        game.emit(died);
+        game.emit(used);
+        game.tick(TICK);
+        assert_eq!(
+            *log.borrow(),
+            [(first, died), (second, died), (first, used), (second, used)]
+        );
+    }
+
+    #[test]
+    fn delivers_events_emitted_during_delivery_next_tick() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut game = test_game();
+        let id = game.entities.add(test_entity(vec![
+            Box::new(HealthComponent { hp: 1 }),
+            Box::new(Recorder(Rc::clone(&log))),
+        ]));
+        let damaged = Event::Damaged {
+            target: id,
+            source: id,
+            amount: 1,
+        };
+        game.emit(damaged);
+        game.tick(TICK);
+        assert_eq!(*log.borrow(), [(id, damaged)]);
+        game.tick(TICK);
+        assert_eq!(
+            *log.borrow(),
+            [(id, damaged), (id, Event::Died { entity: id })]
+        );
+    }
+
+    #[test]
+    fn despawns_entities_damaged_to_death() {
+        let mut game = test_game();
+        let id = game.entities.add(test_entity(vec![
+            Box::new(HealthComponent { hp: 3 }),
+            Box::new(DeathComponent::new("assets/sounds/death.wav")),
+        ]));
+        let bystander = game
+            .entities
+            .add(test_entity(vec![Box::new(HealthComponent { hp: 3 })]));
+        game.emit(Event::Damaged {
+            target: id,
+            source: bystander,
+            amount: 5,
+        });
+        game.tick(TICK);
+        assert_eq!(
+            game.entities
+                .get(id)
+                .unwrap()
+                .get::<HealthComponent>()
+                .unwrap()
+                .hp,
+            -2
+        );
+        game.tick(TICK);
+        assert!(game.entities.get(id).is_none());
+        assert!(game.entities.get(bystander).is_some());
+    }
+}
@@ -1,7 +1,9 @@
-use crate::drawing::val_from_rgba;
+use crate::drawing::{rgba_from_val, val_from_rgba};
 use crate::Surface;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
 
 pub fn load_png(path: &str) -> Result<Surface, Box<dyn Error>> {
     let decoder = png::Decoder::new(File::open(path)?);
@@ -29,3 +31,25 @@ pub fn load_png(path: &str) -> Result<Surface, Box<dyn Error>> {
         pixel_buffer: buf,
     })
 }
+
+pub fn save_png(surface: &Surface, path: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        surface.width as u32,
+        surface.height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+
+    let bytes: Vec<u8> = surface
+        .pixel_buffer
+        .iter()
+        .flat_map(|&val| rgba_from_val(val))
+        .collect();
+    writer.write_image_data(&bytes)?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use glam::*;
+use serde::Deserialize;
+
+use crate::{
+    drawing::{rgba_from_val, val_from_rgb},
+    math::set_value_color,
+};
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum FogMode {
+    /// No fog colour, surfaces only get darker as `1 / sqrt(distance) +
+    /// 0.2` with lights added on top.
+    Falloff,
+    /// No fog before `start`, only fog after `end`.
+    Linear { start: f32, end: f32 },
+    /// Visibility falls off as `e^(-density * distance)`.
+    Exponential { density: f32 },
+}
+
+/// Blends surfaces towards `color` with distance. Used for walls, floors,
+/// ceilings and sprites alike so everything fades out the same way, except
+/// in `Falloff` where sprites stay at full brightness.
+#[derive(Clone, Copy, Deserialize)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vec3,
+}
+impl Default for Fog {
+    fn default() -> Self {
+        Fog {
+            mode: FogMode::Falloff,
+            color: Vec3::ZERO,
+        }
+    }
+}
+impl Fog {
+    /// How much of a surface at `distance` is still visible, 1.0 is no fog.
+    pub fn visibility(&self, distance: f32) -> f32 {
+        match self.mode {
+            FogMode::Falloff => (distance.recip().sqrt() + 0.2).min(1.0),
+            FogMode::Linear { start, end } => {
+                1.0 - ((distance - start) / (end - start).max(f32::EPSILON)).clamp(0.0, 1.0)
+            }
+            FogMode::Exponential { density } => (-density * distance.max(0.0)).exp(),
+        }
+    }
+    /// Lights `val` by `light` and fogs the result for `distance`.
+    #[inline]
+    pub fn shade(&self, val: u32, light: Vec3, distance: f32) -> u32 {
+        let visibility = self.visibility(distance);
+        if let FogMode::Falloff = self.mode {
+            return set_value_color(val, light - Vec3::ONE + visibility);
+        }
+        let lit = set_value_color(val, light * visibility);
+        if visibility >= 1.0 {
+            return lit;
+        }
+        let fog = (self.color * (1.0 - visibility) * 255.0).as_uvec3();
+        let [r, g, b, _] = rgba_from_val(lit);
+        val_from_rgb(r as u32 + fog.x, g as u32 + fog.y, b as u32 + fog.z)
+    }
+    /// Like `shade`, but `Falloff` only lights sprites and never darkens them.
+    #[inline]
+    pub fn shade_sprite(&self, val: u32, light: Vec3, distance: f32) -> u32 {
+        match self.mode {
+            FogMode::Falloff => set_value_color(val, light),
+            _ => self.shade(val, light, distance),
+        }
+    }
+}
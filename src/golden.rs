@@ -0,0 +1,402 @@
+//! Golden-image regression tests for the raycaster.
+//!
+//! Every test renders a single frame of a small map in `tests/maps` from a
+//! fixed camera and compares it against `tests/golden/<name>.png`. Run with
+//! `BLESS=1 cargo test` to (re)generate the golden images after an
+//! intentional rendering change. On a mismatch the actual frame and a diff
+//! image are written to `target/golden`.
+
+use std::{collections::HashMap, f32::consts::FRAC_PI_2, sync::Arc};
+
+use glam::*;
+
+use crate::{
+    components::{CameraComponent, Component},
+    drawing::{rgba_from_val, val_from_rgb},
+    entity::Entity,
+    file::{load_png, save_png},
+    fog::{Fog, FogMode},
+    lighting::{Light, LightMap},
+    prefab::Prefabs,
+    tile_map::{load_map, Surfaces, TileMap, TileType},
+    Audio, Game, HeadlessPlatform, Surface,
+};
+
+const WIDTH: usize = 320;
+const HEIGHT: usize = 240;
+
+/// Largest difference allowed in a single colour channel before a pixel
+/// counts as changed.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels that may change before the comparison fails.
+const PIXEL_TOLERANCE: f32 = 0.001;
+
+struct View {
+    pos: Vec2,
+    look_angle: f32,
+    look_pitch: f32,
+}
+
+/// Renders `tile_map` from `view` with a sprite entity at every position in
+/// `sprites`.
+fn render_view(
+    tile_map: TileMap<'static>,
+    view: View,
+    sprites: &[(Vec2, &'static str)],
+) -> Surface {
+    let mut game = Game::new(
+        Box::new(HeadlessPlatform::new(None)),
+        Audio::silent(),
+        tile_map,
+        WIDTH,
+        HEIGHT,
+    );
+    for &(pos, sprite) in sprites {
+        game.add_entity(Entity::new(
+            pos,
+            Some(sprite),
+            Vec2::ZERO,
+            0.6,
+            true,
+            vec![],
+        ));
+    }
+    let mut camera = CameraComponent::new();
+    // the camera sits on an entity as big as the player, without its
+    // components
+    let prefabs = Prefabs::load("assets/prefabs.ron").unwrap();
+    let size = prefabs.get("player").unwrap().size;
+    let mut entity = Entity::new(view.pos, None, Vec2::ZERO, size, false, vec![]);
+    entity.look_angle = view.look_angle;
+    entity.look_pitch = view.look_pitch;
+
+    camera.render(&entity, &mut game);
+    game.renderer.render(&mut game.screen, &mut game.assets);
+    game.screen
+}
+
+fn assert_golden(name: &str, actual: &Surface) {
+    let golden_path = format!("tests/golden/{name}.png");
+    if std::env::var_os("BLESS").is_some() {
+        save_png(actual, &golden_path).unwrap();
+        return;
+    }
+    let golden = load_png(&golden_path).unwrap_or_else(|err| {
+        panic!("couldn't load {golden_path} ({err}), run with BLESS=1 to create it")
+    });
+    assert_eq!(
+        (golden.width, golden.height),
+        (actual.width, actual.height),
+        "{name}: size differs from golden image"
+    );
+
+    let mut diff = Surface::empty(actual.width, actual.height);
+    let mut changed = 0;
+    for (i, (&want, &got)) in golden
+        .pixel_buffer
+        .iter()
+        .zip(&actual.pixel_buffer)
+        .enumerate()
+    {
+        let want = rgba_from_val(want);
+        let got = rgba_from_val(got);
+        let max_delta = want
+            .iter()
+            .zip(&got)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap();
+        diff.pixel_buffer[i] = if max_delta > CHANNEL_TOLERANCE {
+            changed += 1;
+            val_from_rgb(255, 0, 0)
+        } else {
+            // dimmed greyscale copy of the frame so the red stands out
+            let grey = (want[0] as u32 + want[1] as u32 + want[2] as u32) / 9;
+            val_from_rgb(grey, grey, grey)
+        };
+    }
+
+    let allowed = (PIXEL_TOLERANCE * actual.pixel_buffer.len() as f32) as usize;
+    if changed > allowed {
+        let actual_path = format!("target/golden/{name}.actual.png");
+        let diff_path = format!("target/golden/{name}.diff.png");
+        save_png(actual, &actual_path).unwrap();
+        save_png(&diff, &diff_path).unwrap();
+        panic!(
+            "{name}: {changed} pixels differ from {golden_path} (allowed {allowed}), \
+             see {actual_path} and {diff_path}"
+        );
+    }
+}
+
+fn load_test_map(name: &str) -> TileMap<'static> {
+    load_map(&format!("tests/maps/{name}.txt")).unwrap()
+}
+
+#[test]
+fn door_closed() {
+    let frame = render_view(
+        load_test_map("doors"),
+        View {
+            pos: vec2(4.5, 4.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("door_closed", &frame);
+}
+
+#[test]
+fn door_half_open() {
+    let mut tile_map = load_test_map("doors");
+    if let Some(tile) = tile_map.get_tile_mut(ivec2(4, 2)) {
+        if let TileType::Door(ref mut open_amount, _) = tile.tile_type {
+            *open_amount = 0.5;
+        }
+    }
+    let frame = render_view(
+        tile_map,
+        View {
+            pos: vec2(4.5, 4.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("door_half_open", &frame);
+}
+
+#[test]
+fn door_vertical() {
+    let frame = render_view(
+        load_test_map("doors"),
+        View {
+            pos: vec2(5.5, 4.5),
+            look_angle: -FRAC_PI_2,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("door_vertical", &frame);
+}
+
+#[test]
+fn subwalls_horizontal() {
+    let frame = render_view(
+        load_test_map("subwalls"),
+        View {
+            pos: vec2(4.5, 4.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("subwalls_horizontal", &frame);
+}
+
+#[test]
+fn subwall_vertical() {
+    let frame = render_view(
+        load_test_map("subwalls"),
+        View {
+            pos: vec2(6.5, 4.5),
+            look_angle: -FRAC_PI_2,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("subwall_vertical", &frame);
+}
+
+#[test]
+fn sprite_clipped_by_wall_edge() {
+    let frame = render_view(
+        load_test_map("sprites"),
+        View {
+            pos: vec2(4.5, 5.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[(vec2(4.0, 1.5), "assets/guy.png")],
+    );
+    assert_golden("sprite_clipped_by_wall_edge", &frame);
+}
+
+#[test]
+fn lit_by_static_lights() {
+    let mut tile_map = load_test_map("sprites");
+    let lights = [
+        Light {
+            pos: vec2(2.5, 4.5),
+            color: vec3(0.8, 0.3, 0.1),
+            radius: 4.0,
+        },
+        Light {
+            pos: vec2(6.5, 4.5),
+            color: vec3(0.1, 0.3, 0.9),
+            radius: 3.0,
+        },
+    ];
+    tile_map.light_map = Arc::new(LightMap::bake(&tile_map, &lights));
+    let frame = render_view(
+        tile_map,
+        View {
+            pos: vec2(4.5, 5.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[(vec2(2.5, 1.5), "assets/guy.png")],
+    );
+    assert_golden("lit_by_static_lights", &frame);
+}
+
+#[test]
+fn linear_grey_fog() {
+    let mut tile_map = load_test_map("subwalls");
+    tile_map.fog = Fog {
+        mode: FogMode::Linear {
+            start: 1.0,
+            end: 6.0,
+        },
+        color: vec3(0.6, 0.6, 0.65),
+    };
+    let frame = render_view(
+        tile_map,
+        View {
+            pos: vec2(8.5, 4.5),
+            look_angle: -FRAC_PI_2,
+            look_pitch: 0.0,
+        },
+        &[(vec2(4.5, 4.5), "assets/guy.png")],
+    );
+    assert_golden("linear_grey_fog", &frame);
+}
+
+#[test]
+fn tall_wall_behind_low_walls() {
+    let frame = render_view(
+        load_test_map("heights"),
+        View {
+            pos: vec2(4.5, 5.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[(vec2(4.5, 2.5), "assets/guy.png")],
+    );
+    assert_golden("tall_wall_behind_low_walls", &frame);
+}
+
+#[test]
+fn per_cell_floors_and_open_sky() {
+    let mut tile_map = load_test_map("sprites");
+    tile_map.fog = Fog {
+        mode: FogMode::Exponential { density: 0.1 },
+        color: vec3(0.4, 0.6, 0.9),
+    };
+    let legend = HashMap::from([
+        (
+            's',
+            Surfaces {
+                floor: "assets/white.png",
+                ceiling: None,
+            },
+        ),
+        (
+            'b',
+            Surfaces {
+                floor: "assets/bricksmall.png",
+                ceiling: Some("assets/wood.png"),
+            },
+        ),
+    ]);
+    tile_map
+        .parse_surfaces(
+            &[
+                "         ",
+                " sss     ",
+                " sss     ",
+                " sss     ",
+                "     bbb ",
+                "     bbb ",
+                "         ",
+            ],
+            &legend,
+        )
+        .unwrap();
+    let frame = render_view(
+        tile_map,
+        View {
+            pos: vec2(4.5, 5.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("per_cell_floors_and_open_sky", &frame);
+}
+
+#[test]
+fn skybox_through_gap_and_open_ceiling() {
+    let mut tile_map = load_test_map("open");
+    tile_map.sky_texture = Some("assets/sky.png");
+    let legend = HashMap::from([(
+        's',
+        Surfaces {
+            floor: "assets/floor.png",
+            ceiling: None,
+        },
+    )]);
+    tile_map
+        .parse_surfaces(
+            &[
+                "         ",
+                " sssssss ",
+                " sssssss ",
+                " sss     ",
+                "         ",
+                "         ",
+                "         ",
+            ],
+            &legend,
+        )
+        .unwrap();
+    let frame = render_view(
+        tile_map,
+        View {
+            pos: vec2(3.0, 5.5),
+            look_angle: 0.0,
+            look_pitch: 0.0,
+        },
+        &[],
+    );
+    assert_golden("skybox_through_gap_and_open_ceiling", &frame);
+}
+
+#[test]
+fn looking_up_shears_the_view() {
+    let frame = render_view(
+        load_test_map("heights"),
+        View {
+            pos: vec2(4.5, 5.5),
+            look_angle: 0.0,
+            look_pitch: 0.3,
+        },
+        &[(vec2(4.5, 2.5), "assets/guy.png")],
+    );
+    assert_golden("looking_up_shears_the_view", &frame);
+}
+
+#[test]
+fn looking_down_shears_the_view() {
+    let frame = render_view(
+        load_test_map("heights"),
+        View {
+            pos: vec2(4.5, 5.5),
+            look_angle: 0.0,
+            look_pitch: -0.3,
+        },
+        &[(vec2(4.5, 2.5), "assets/guy.png")],
+    );
+    assert_golden("looking_down_shears_the_view", &frame);
+}
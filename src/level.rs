@@ -0,0 +1,396 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use glam::*;
+use serde::Deserialize;
+
+use crate::{
+    components::*,
+    depth_buffer::Direction,
+    entity::Entity,
+    fog::Fog,
+    lighting::{Light, LightMap, LightSource},
+    tile_map::{
+        default_legend, load_map, parse_grid, SpawnKind, Tile, TileMap, TileType,
+        Surfaces, DEFAULT_CEILING_TEXTURE, DEFAULT_FLOOR_TEXTURE,
+    },
+    validation::{validate_level, MapError},
+};
+
+/// A level as written in a `.ron` file, see `assets/levels/level1.ron`.
+#[derive(Deserialize)]
+pub struct LevelFile {
+    #[serde(default)]
+    pub properties: LevelProperties,
+    /// Extends and overrides the built-in legend of `tile_map::load_map`.
+    #[serde(default)]
+    pub legend: HashMap<char, TileDef>,
+    pub grid: GridDef,
+    /// Per cell floors and ceilings, laid over `grid`.
+    #[serde(default)]
+    pub surfaces: Option<SurfaceLayer>,
+    /// Overrides a `P` marker in the grid.
+    #[serde(default)]
+    pub player_start: Option<PlayerStart>,
+    #[serde(default)]
+    pub entities: Vec<EntityDef>,
+    /// Entities from `assets/prefabs.ron`.
+    #[serde(default)]
+    pub prefabs: Vec<PrefabSpawn>,
+    /// Doors that start opening as soon as the level is loaded.
+    #[serde(default)]
+    pub open_doors: Vec<(usize, usize)>,
+    /// Static lights, baked into the tile map when the level is loaded.
+    #[serde(default)]
+    pub lights: Vec<Light>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LevelProperties {
+    pub floor: String,
+    pub ceiling: String,
+    pub sky: Option<String>,
+    pub music: Option<String>,
+    pub fog: Fog,
+}
+impl Default for LevelProperties {
+    fn default() -> Self {
+        LevelProperties {
+            floor: DEFAULT_FLOOR_TEXTURE.to_string(),
+            ceiling: DEFAULT_CEILING_TEXTURE.to_string(),
+            sky: None,
+            music: None,
+            fog: Fog::default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub enum GridDef {
+    /// Rows of glyphs written inline.
+    Rows(Vec<String>),
+    /// Path to a plain ASCII map.
+    File(String),
+}
+impl GridDef {
+    fn rows(&self) -> Result<Vec<String>, MapError> {
+        Ok(match self {
+            GridDef::Rows(rows) => rows.clone(),
+            GridDef::File(path) => read_to_string(path)?.lines().map(str::to_string).collect(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SurfaceLayer {
+    pub legend: HashMap<char, SurfaceDef>,
+    /// Same size as the tile grid, spaces keep the level's floor and ceiling.
+    pub grid: GridDef,
+}
+#[derive(Deserialize)]
+pub struct SurfaceDef {
+    /// Defaults to the level's floor.
+    #[serde(default)]
+    pub floor: Option<String>,
+    #[serde(default)]
+    pub ceiling: CeilingDef,
+}
+#[derive(Deserialize, Default)]
+pub enum CeilingDef {
+    /// The level's ceiling.
+    #[default]
+    Level,
+    Texture(String),
+    Sky,
+}
+impl SurfaceDef {
+    fn to_surfaces(&self, properties: &LevelProperties) -> Surfaces<'static> {
+        Surfaces {
+            floor: leak(self.floor.as_ref().unwrap_or(&properties.floor)),
+            ceiling: match &self.ceiling {
+                CeilingDef::Level => Some(leak(&properties.ceiling)),
+                CeilingDef::Texture(texture) => Some(leak(texture)),
+                CeilingDef::Sky => None,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PlayerStart {
+    pub pos: (f32, f32),
+    #[serde(default)]
+    pub look_angle: f32,
+}
+
+#[derive(Deserialize)]
+pub struct TileDef {
+    pub kind: TileKind,
+    /// Textures for the horizontal and vertical faces.
+    pub textures: [String; 2],
+    #[serde(default)]
+    pub passable: bool,
+    #[serde(default)]
+    pub projectile_passable: bool,
+    /// Height of the bottom edge above the floor, in tiles.
+    #[serde(default)]
+    pub elevation: f32,
+    #[serde(default = "default_tile_height")]
+    pub height: f32,
+}
+fn default_tile_height() -> f32 {
+    1.0
+}
+#[derive(Deserialize)]
+pub enum TileKind {
+    Wall,
+    Subwall { offset: f32, direction: Direction },
+    Door { direction: Direction },
+}
+impl TileDef {
+    fn to_tile(&self) -> Tile<'static> {
+        Tile {
+            tile_type: match self.kind {
+                TileKind::Wall => TileType::Wall,
+                TileKind::Subwall { offset, direction } => TileType::Subwall(offset, direction),
+                TileKind::Door { direction } => TileType::Door(0.0, direction),
+            },
+            passable: self.passable,
+            projectile_passable: self.projectile_passable,
+            sprites: [leak(&self.textures[0]), leak(&self.textures[1])],
+            elevation: self.elevation,
+            height: self.height,
+        }
+    }
+}
+
+/// An entity of the prefab `prefab` centred on `pos`.
+#[derive(Deserialize, Clone)]
+pub struct PrefabSpawn {
+    pub prefab: String,
+    pub pos: (f32, f32),
+}
+impl PrefabSpawn {
+    /// What the `E` and `K` markers of an ASCII map spawn.
+    pub fn from_marker(kind: SpawnKind, pos: Vec2) -> Option<Self> {
+        let prefab = match kind {
+            SpawnKind::Player => return None,
+            SpawnKind::Enemy => "grunt",
+            SpawnKind::Key => "key",
+        };
+        Some(PrefabSpawn {
+            prefab: prefab.to_string(),
+            pos: pos.into(),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EntityDef {
+    pub pos: (f32, f32),
+    pub sprite: Option<String>,
+    #[serde(default)]
+    pub vel: (f32, f32),
+    pub size: f32,
+    #[serde(default)]
+    pub collidable: bool,
+    #[serde(default)]
+    pub components: Vec<ComponentDef>,
+    #[serde(default)]
+    pub light: Option<LightSource>,
+}
+impl EntityDef {
+    pub fn build(&self) -> Entity<'static> {
+        let mut entity = Entity::new(
+            self.pos.into(),
+            self.sprite.as_deref().map(leak),
+            self.vel.into(),
+            self.size,
+            self.collidable,
+            self.components.iter().map(ComponentDef::build).collect(),
+        );
+        entity.light = self.light;
+        entity
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub enum ComponentDef {
+    BasicCollision,
+    PlayerInput,
+    Camera,
+    BasicAi,
+    PathFollow {
+        speed: f32,
+    },
+    EnemyAi {
+        /// Points walked between in turn while nothing is going on.
+        #[serde(default)]
+        patrol: Vec<(f32, f32)>,
+        #[serde(default)]
+        settings: AiSettings,
+    },
+    Faction(String),
+    Health {
+        hp: i32,
+    },
+    Death {
+        sound: String,
+    },
+    Animation {
+        images: Vec<String>,
+        time_per_frame: f32,
+    },
+}
+impl ComponentDef {
+    pub fn build(&self) -> Box<dyn Component> {
+        match self {
+            ComponentDef::BasicCollision => Box::new(BasicCollisionComponent),
+            ComponentDef::PlayerInput => Box::new(PlayerInputComponent),
+            ComponentDef::Camera => Box::new(CameraComponent::new()),
+            ComponentDef::BasicAi => Box::new(BasicAiComponent),
+            ComponentDef::PathFollow { speed } => Box::new(PathFollowComponent::new(*speed)),
+            ComponentDef::EnemyAi { patrol, settings } => Box::new(EnemyAiComponent::new(
+                *settings,
+                patrol.iter().map(|&point| point.into()).collect(),
+            )),
+            ComponentDef::Faction(name) => Box::new(FactionComponent { name: leak(name) }),
+            ComponentDef::Health { hp } => Box::new(HealthComponent { hp: *hp }),
+            ComponentDef::Death { sound } => Box::new(DeathComponent::new(leak(sound))),
+            ComponentDef::Animation {
+                images,
+                time_per_frame,
+            } => Box::new(AnimationComponent {
+                images: images.iter().map(|image| leak(image)).collect(),
+                time_per_frame: *time_per_frame,
+                cur_time: 0.0,
+            }),
+        }
+    }
+}
+
+/// A loaded level, ready to be handed to `Game`.
+pub struct Level<'a> {
+    pub tile_map: TileMap<'a>,
+    pub player_start: Vec2,
+    pub look_angle: f32,
+    pub entities: Vec<EntityDef>,
+    pub prefabs: Vec<PrefabSpawn>,
+    pub music: Option<&'a str>,
+}
+
+impl<'a> Level<'a> {
+    /// Turns the spawn markers of `tile_map` into a player start and prefab
+    /// spawns. `player_start` takes precedence over a `P` marker.
+    pub fn from_tile_map(
+        tile_map: TileMap<'a>,
+        player_start: Option<PlayerStart>,
+        entities: Vec<EntityDef>,
+        mut prefabs: Vec<PrefabSpawn>,
+        music: Option<&'a str>,
+    ) -> Result<Self, MapError> {
+        let marker_start = tile_map
+            .spawns
+            .iter()
+            .find(|spawn| spawn.kind == SpawnKind::Player)
+            .map(|spawn| spawn.pos);
+        let (player_start, look_angle) = match (player_start, marker_start) {
+            (Some(start), _) => (start.pos.into(), start.look_angle),
+            (None, Some(pos)) => (pos, 0.0),
+            (None, None) => return Err(MapError::NoPlayerStart),
+        };
+        prefabs.extend(
+            tile_map
+                .spawns
+                .iter()
+                .filter_map(|spawn| PrefabSpawn::from_marker(spawn.kind, spawn.pos)),
+        );
+        Ok(Level {
+            tile_map,
+            player_start,
+            look_angle,
+            entities,
+            prefabs,
+            music,
+        })
+    }
+}
+
+/// Loads and validates a `.ron` level, or a bare ASCII map when the path
+/// ends in `.txt`.
+pub fn load_level<'a>(path: &str) -> Result<Level<'a>, MapError> {
+    let level = if path.ends_with(".txt") {
+        Level::from_tile_map(load_map(path)?, None, Vec::new(), Vec::new(), None)?
+    } else {
+        load_level_file(path)?
+    };
+    validate_level(&level)?;
+    Ok(level)
+}
+
+fn load_level_file<'a>(path: &str) -> Result<Level<'a>, MapError> {
+    let level: LevelFile =
+        ron::from_str(&read_to_string(path)?).map_err(|source| MapError::Parse {
+            path: path.to_string(),
+            source,
+        })?;
+
+    let mut legend = default_legend();
+    legend.extend(level.legend.iter().map(|(&c, def)| (c, def.to_tile())));
+
+    let rows = level.grid.rows()?;
+    let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+    let mut tile_map = parse_grid(&rows, &legend)?;
+    tile_map.floor_texture = leak(&level.properties.floor);
+    tile_map.ceiling_texture = leak(&level.properties.ceiling);
+    tile_map.sky_texture = level.properties.sky.as_deref().map(leak);
+    if let Some(layer) = &level.surfaces {
+        let legend = layer
+            .legend
+            .iter()
+            .map(|(&c, def)| (c, def.to_surfaces(&level.properties)))
+            .collect();
+        let rows = layer.grid.rows()?;
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        tile_map.parse_surfaces(&rows, &legend)?;
+    }
+    tile_map.fog = level.properties.fog;
+    tile_map.light_map = Arc::new(LightMap::bake(&tile_map, &level.lights));
+    for (x, y) in level.open_doors {
+        tile_map.tile_update_indeces.push(x + y * tile_map.width);
+    }
+
+    Level::from_tile_map(
+        tile_map,
+        level.player_start,
+        level.entities,
+        level.prefabs,
+        level.properties.music.as_deref().map(leak),
+    )
+}
+
+fn read_to_string(path: &str) -> Result<String, MapError> {
+    fs::read_to_string(path).map_err(|source| MapError::Io {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Level data is loaded once and used for the rest of the run, so the
+/// strings are leaked to hand out the `&'static str`s entities and tiles use.
+/// Prefabs are built over and over, so every distinct string is only leaked
+/// once.
+pub fn leak(s: &str) -> &'static str {
+    static LEAKED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut leaked = LEAKED.lock().unwrap();
+    if let Some(&s) = leaked.get(s) {
+        return s;
+    }
+    let s = Box::leak(s.to_string().into_boxed_str());
+    leaked.insert(s);
+    s
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use glam::*;
+use serde::Deserialize;
+
+use crate::tile_map::{TileMap, TileType};
+
+/// A point light. `color` is added on top of the distance shading, so a
+/// channel of 1.0 is enough to light a surface fully.
+#[derive(Clone, Copy, Deserialize)]
+pub struct Light {
+    pub pos: Vec2,
+    pub color: Vec3,
+    pub radius: f32,
+}
+impl Light {
+    pub fn at(&self, pos: Vec2) -> Vec3 {
+        let falloff = 1.0 - self.pos.distance(pos) / self.radius;
+        if falloff <= 0.0 {
+            return Vec3::ZERO;
+        }
+        self.color * falloff
+    }
+}
+
+/// A light carried by an entity, placed at the entity's position every frame.
+#[derive(Clone, Copy, Deserialize)]
+pub struct LightSource {
+    pub color: Vec3,
+    pub radius: f32,
+}
+impl LightSource {
+    pub fn at(&self, pos: Vec2) -> Light {
+        Light {
+            pos,
+            color: self.color,
+            radius: self.radius,
+        }
+    }
+}
+
+/// Static lights baked into one value per tile. Walls block light, doors and
+/// subwalls don't.
+#[derive(Default)]
+pub struct LightMap {
+    width: usize,
+    height: usize,
+    cells: Vec<Vec3>,
+}
+impl LightMap {
+    pub fn bake(tile_map: &TileMap, lights: &[Light]) -> Self {
+        let mut cells = vec![Vec3::ZERO; tile_map.width * tile_map.height];
+        for y in 0..tile_map.height {
+            for x in 0..tile_map.width {
+                let cell = ivec2(x as i32, y as i32);
+                if is_opaque(tile_map, cell) {
+                    continue;
+                }
+                let center = cell.as_vec2() + 0.5;
+                cells[x + y * tile_map.width] = lights
+                    .iter()
+                    .filter(|light| light_reaches(tile_map, light.pos, center))
+                    .map(|light| light.at(center))
+                    .sum();
+            }
+        }
+        LightMap {
+            width: tile_map.width,
+            height: tile_map.height,
+            cells,
+        }
+    }
+    pub fn get(&self, pos: Vec2) -> Vec3 {
+        let cell = pos.floor().as_ivec2();
+        if 0 <= cell.x && cell.x < self.width as i32 && 0 <= cell.y && cell.y < self.height as i32 {
+            return self.cells[cell.x as usize + cell.y as usize * self.width];
+        }
+        Vec3::ZERO
+    }
+}
+
+fn is_opaque(tile_map: &TileMap, cell: IVec2) -> bool {
+    tile_map
+        .get_tile(cell)
+        .is_some_and(|tile| tile.tile_type == TileType::Wall)
+}
+
+fn light_reaches(tile_map: &TileMap, from: Vec2, to: Vec2) -> bool {
+    let steps = (from.distance(to) / 0.1).ceil() as usize;
+    (1..steps).all(|i| !is_opaque(tile_map, from.lerp(to, i as f32 / steps as f32).as_ivec2()))
+}
+
+/// Baked lighting plus the dynamic lights of the current frame, cheap to
+/// clone into render jobs.
+#[derive(Clone)]
+pub struct Lighting {
+    pub light_map: Arc<LightMap>,
+    pub dynamic: Arc<Vec<Light>>,
+}
+impl Lighting {
+    pub fn at(&self, pos: Vec2) -> Vec3 {
+        self.light_map.get(pos) + self.dynamic.iter().map(|light| light.at(pos)).sum::<Vec3>()
+    }
+}
@@ -1,27 +1,19 @@
 use kira::dsp::Frame;
-use minifb::{Key, Window, WindowOptions};
-use std::cell::Ref;
-use std::rc::Rc;
 use std::{cell::RefCell, collections::HashMap};
 
 use std::sync::Arc;
 use std::time;
 
 use glam::*;
-use kira::{
-    manager::{backend::DefaultBackend, AudioManager, AudioManagerSettings},
-    sound::static_sound::{StaticSoundData, StaticSoundSettings},
-};
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
 use simple_logger::SimpleLogger;
 
 mod math;
-use math::set_value_brightness;
 
 mod surface;
 use surface::*;
 
 mod drawing;
-use drawing::*;
 
 mod file;
 use file::*;
@@ -32,18 +24,64 @@ use depth_buffer::*;
 mod entity;
 use entity::*;
 
+mod commands;
+use commands::*;
+
+mod events;
+use events::*;
+
 mod rect;
 
 mod tile_map;
 use tile_map::*;
 
+mod level;
+use level::*;
+
+mod validation;
+
+mod navigation;
+use navigation::*;
+
+mod raycast;
+use raycast::*;
+
+mod lighting;
+
+mod fog;
+
 mod components;
 use components::*;
 
+mod platform;
+use platform::*;
+
+mod audio;
+use audio::*;
+
+mod capture;
+use capture::*;
+
+mod controls;
+use controls::*;
+
+mod demo;
+use demo::*;
+
+mod prefab;
+use prefab::*;
+
+#[cfg(test)]
+mod golden;
+
 const WIDTH: usize = 700 * 2;
 const HEIGHT: usize = 400 * 2;
 const SCALE: usize = 1;
-const PLAYER_SIZE: f32 = 0.8;
+/// Length of a simulation tick in seconds.
+const TICK: f32 = 1.0 / 60.0;
+/// Ticks run per rendered frame at most, time beyond that is dropped so a
+/// long stall slows the game down instead of freezing it while it catches up.
+const MAX_TICKS_PER_FRAME: u32 = 5;
 
 pub struct AssetCache {
     sprites: RefCell<HashMap<String, Arc<Surface>>>,
@@ -97,45 +135,218 @@ impl AssetCache {
 }
 
 pub struct Game<'a> {
-    pub window: Window,
+    pub platform: Box<dyn Platform>,
     pub renderer: DepthBufferRenderer<'a>,
     pub tile_map: TileMap<'a>,
 
     pub screen: Surface,
     pub assets: AssetCache,
-    pub audio_manager: AudioManager,
-    pub entities: HashMap<u32, Entity<'a>>,
-    next_id: u32,
+    pub audio: Audio,
+    pub entities: Entities<'a>,
+    /// Spawns and despawns queued during the current tick.
+    pub commands: Commands<'a>,
+    pub events: Events,
+    pub prefabs: Prefabs,
+    pub navigation: Navigation,
+    pub controls: Controls,
+    /// What the player is doing this frame, polled through `controls` or
+    /// read from a demo.
+    pub actions: Actions,
 }
 impl<'a> Game<'a> {
-    fn new() -> Self {
+    fn new(
+        platform: Box<dyn Platform>,
+        audio: Audio,
+        tile_map: TileMap<'a>,
+        width: usize,
+        height: usize,
+    ) -> Self {
         Game {
-            window: Window::new(
+            platform,
+            renderer: DepthBufferRenderer::new(width + 10, tile_map.fog),
+            entities: Entities::default(),
+            commands: Commands::default(),
+            events: Events::default(),
+            prefabs: Prefabs::default(),
+            navigation: Navigation::new(&tile_map),
+            tile_map,
+            screen: Surface::empty(width, height),
+            assets: AssetCache::new(),
+            audio,
+            controls: Controls::default(),
+            actions: Actions::default(),
+        }
+    }
+    fn windowed(tile_map: TileMap<'a>) -> Self {
+        Game::new(
+            Box::new(MinifbPlatform::new(
                 "Test - ESC to exit",
                 WIDTH * SCALE,
                 HEIGHT * SCALE,
-                WindowOptions {
-                    scale_mode: minifb::ScaleMode::AspectRatioStretch,
-                    ..Default::default()
-                },
-            )
-            .unwrap_or_else(|e| {
-                panic!("{}", e);
-            }),
-            renderer: DepthBufferRenderer::new(WIDTH + 10),
-            entities: HashMap::new(),
-            tile_map: load_map("assets/map.txt").expect("couldn't load map"),
-            screen: Surface::empty(WIDTH, HEIGHT),
-            assets: AssetCache::new(),
-            audio_manager: AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())
-                .unwrap(),
-            next_id: 0,
+            )),
+            Audio::new(),
+            tile_map,
+            WIDTH,
+            HEIGHT,
+        )
+    }
+    /// A game that never opens a window or an audio device. `max_frames`
+    /// closes the platform after that many presented frames and `script` is
+    /// the input of the first frames, one entry per frame.
+    fn headless(
+        tile_map: TileMap<'a>,
+        max_frames: Option<usize>,
+        script: Vec<ScriptedInput>,
+    ) -> Self {
+        let mut platform = HeadlessPlatform::new(max_frames);
+        for input in script {
+            platform.push_input(input);
+        }
+        Game::new(
+            Box::new(platform),
+            Audio::silent(),
+            tile_map,
+            WIDTH,
+            HEIGHT,
+        )
+    }
+    /// Adds an entity right away, only for setting up a level. Use `spawn`
+    /// during a tick.
+    fn add_entity(&mut self, entity: Entity<'a>) {
+        self.entities.add(entity);
+    }
+    /// Adds the player at `player_start` followed by the entities and prefab
+    /// spawns of a level.
+    fn add_level_entities(
+        &mut self,
+        player_start: Vec2,
+        look_angle: f32,
+        entities: &[EntityDef],
+        prefabs: &[PrefabSpawn],
+    ) -> Result<(), PrefabError> {
+        let mut player = self.prefabs.build("player", player_start)?;
+        player.look_angle = look_angle;
+        self.add_entity(player);
+        for entity in entities {
+            self.add_entity(entity.build());
+        }
+        for spawn in prefabs {
+            let entity = self.prefabs.build(&spawn.prefab, spawn.pos.into())?;
+            self.add_entity(entity);
+        }
+        Ok(())
+    }
+    /// Queues `entity` to be added at the end of the tick and returns the id
+    /// it will have.
+    pub fn spawn(&mut self, mut entity: Entity<'a>) -> u32 {
+        entity.id = self.entities.reserve_id();
+        let id = entity.id;
+        self.commands.spawn(entity);
+        id
+    }
+    /// Queues an entity of the prefab `name` centred on `pos`, see `spawn`.
+    pub fn spawn_prefab(&mut self, name: &str, pos: Vec2) -> Result<u32, PrefabError> {
+        let entity = self.prefabs.build(name, pos)?;
+        Ok(self.spawn(entity))
+    }
+    /// Queues the entity `id` to be removed at the end of the tick.
+    pub fn despawn(&mut self, id: u32) {
+        self.commands.despawn(id);
+    }
+    /// Queues `component` to be added to the entity `id` at the end of the
+    /// tick.
+    pub fn add_component(&mut self, id: u32, component: Box<dyn Component>) {
+        self.commands.add_component(id, component);
+    }
+
+    /// The first tile or collidable entity other than `ignore` within
+    /// `max_distance` of `origin` along `dir`, see `raycast::cast_ray`.
+    pub fn cast_ray(
+        &self,
+        origin: Vec2,
+        dir: Vec2,
+        max_distance: f32,
+        ignore: Option<u32>,
+    ) -> Option<RayHit> {
+        cast_ray(&self.tile_map, &self.entities, origin, dir, max_distance, ignore)
+    }
+    /// Whether no tile hides `to` from `from`, see `raycast::cast_sight`.
+    /// Entities don't block the view.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        from == to || cast_sight(&self.tile_map, from, to - from, from.distance(to)).is_none()
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        self.events.emit(event);
+    }
+
+    /// Advances the simulation by one tick of `dt` seconds, reading the
+    /// player's input from `self.actions`: updates every entity, delivers the
+    /// events of the tick to every entity, applies the queued commands and
+    /// updates the tile map and the navigation grid.
+    fn tick(&mut self, dt: f32) {
+        for entity in self.entities.iter_mut() {
+            entity.prev_pos = entity.rect.pos;
+            entity.prev_look_angle = entity.look_angle;
+        }
+
+        for id in self.entities.ids() {
+            let mut entity = self.entities.take_leaving_snapshot(id).unwrap();
+            entity.update(dt, self);
+            self.entities.put_back(entity);
+        }
+        for entity in self.entities.iter() {
+            let tile = entity.rect.pos.floor().as_ivec2();
+            if tile != entity.prev_pos.floor().as_ivec2() {
+                self.events.emit(Event::EnteredTile {
+                    entity: entity.id,
+                    tile,
+                });
+            }
+        }
+
+        // every event goes to every entity, in the order they were emitted
+        for event in self.events.take() {
+            for id in self.entities.ids() {
+                let mut entity = self.entities.take_leaving_snapshot(id).unwrap();
+                entity.on_event(&event, self);
+                self.entities.put_back(entity);
+            }
         }
+        self.commands.apply(&mut self.entities);
+        self.tile_map.update(dt);
+        self.navigation.update(&self.tile_map);
     }
-    fn add_entity(&mut self, mut entity: Entity<'a>) {
-        entity.id = self.next_id;
-        self.entities.insert(self.next_id, entity);
-        self.next_id += 1;
+
+    /// Renders the frame into `screen` with every entity `alpha` of the way
+    /// from where it was before the last tick to where it is now. Presenting
+    /// it is left to the caller.
+    fn render(&mut self, alpha: f32) {
+        self.screen.fill(0);
+
+        let current: Vec<(u32, Vec2, f32)> = self
+            .entities
+            .iter_mut()
+            .map(|entity| {
+                let state = (entity.id, entity.rect.pos, entity.look_angle);
+                entity.rect.pos = entity.prev_pos.lerp(entity.rect.pos, alpha);
+                entity.look_angle =
+                    entity.prev_look_angle + (entity.look_angle - entity.prev_look_angle) * alpha;
+                state
+            })
+            .collect();
+        for &(id, _, _) in &current {
+            let mut entity = self.entities.take(id).unwrap();
+            entity.render(self);
+            self.entities.put_back(entity);
+        }
+        for (id, pos, look_angle) in current {
+            let entity = self.entities.get_mut(id).unwrap();
+            entity.rect.pos = pos;
+            entity.look_angle = look_angle;
+        }
+
+        self.renderer.render(&mut self.screen, &mut self.assets);
     }
 }
 
@@ -147,87 +358,143 @@ fn main() {
         .init()
         .unwrap();
 
-    let mut game = Game::new();
-    let gun_image = load_png("assets/gun.png").unwrap();
-    let music = game.assets.load_sound(
-        "assets/sounds/game_bg.mp3",
-        Some(
-            StaticSoundSettings::default()
-                .volume(0.2)
-                .loop_region(0.0..),
-        ),
+    let args: Vec<String> = std::env::args().collect();
+    let arg_value = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .map(|i| args.get(i + 1).map(String::as_str))
+    };
+    // `--headless <frames>` runs the game without a window or audio device
+    // for a fixed number of frames.
+    let headless_frames = arg_value("--headless")
+        .map(|frames| frames.and_then(|n| n.parse().ok()).unwrap_or(60));
+    // `--input <path>` feeds a headless run the input script at `path`, see
+    // `parse_input_script`
+    let script = arg_value("--input").flatten().map(|path| {
+        load_input_script(path)
+            .unwrap_or_else(|err| panic!("couldn't load input script {path}: {err}"))
+    });
+    if script.is_some() && headless_frames.is_none() {
+        log::warn!("Ignoring --input, input scripts only drive --headless runs");
+    }
+    // `--record <frames>` records that many frames from the start, F11 starts
+    // another recording of the same length and F12 takes a screenshot.
+    let record_frames = arg_value("--record");
+    let mut recorder = FrameRecorder::new(
+        "captures",
+        record_frames
+            .flatten()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(300),
     );
-    game.audio_manager.play(music.clone());
-    game.add_entity(Entity::new(
-        Vec2::new(6.5, 7.5),
-        Some("assets/player.png"),
-        Vec2::new(0.0, 0.0),
-        PLAYER_SIZE,
-        true,
-        vec![
-            Box::new(BasicCollisionComponent),
-            Box::new(PlayerInputComponent),
-            Box::new(CameraComponent::new()),
-        ],
-    ));
-
-    game.add_entity(Entity::new(
-        Vec2::new(9.5, 9.5),
-        Some("assets/player.png"),
-        Vec2::new(0.0, 0.0),
-        0.6,
-        true,
-        vec![
-            Box::new(BasicCollisionComponent),
-            Box::new(BasicAiComponent),
-            Box::new(DeathComponent::new("assets/sounds/death.wav")),
-        ],
-    ));
-    game.add_entity(Entity::new(
-        Vec2::new(10.5, 9.5),
-        Some("assets/guy.png"),
-        Vec2::new(0.0, 0.0),
-        0.6,
-        true,
-        vec![
-            Box::new(BasicCollisionComponent),
-            Box::new(BasicAiComponent),
-            Box::new(DeathComponent::new("assets/sounds/death.wav")),
-        ],
-    ));
-    game.add_entity(Entity::new(
-        Vec2::new(12.5, 8.5),
-        Some("assets/key.png"),
-        Vec2::new(0.0, 0.0),
-        0.2,
-        true,
-        vec![Box::new(BasicCollisionComponent)],
-    ));
-
-    // Limit to max ~60 fps update rate
-    game.window
-        .limit_update_rate(Some(std::time::Duration::from_micros(16600)));
-    game.tile_map
-        .tile_update_indeces
-        .push(6 + 6 * game.tile_map.width);
-    let mut now = time::SystemTime::now();
-    while game.window.is_open() && !game.window.is_key_down(Key::Escape) {
-        let dt = now.elapsed().unwrap().as_secs_f32();
+    if record_frames.is_some() {
+        recorder.start();
+    }
+    // `--controls <path>` picks the key bindings and mouse settings
+    let controls_path = arg_value("--controls")
+        .flatten()
+        .unwrap_or("assets/controls.ron");
+    let controls = Controls::load(controls_path).unwrap_or_else(|err| {
+        log::warn!("Using the default controls, {err}");
+        Controls::default()
+    });
+    // `--play-demo <path>` replays a demo recorded with `--record-demo <path>`,
+    // including its level
+    let demo = arg_value("--play-demo").flatten().map(|path| {
+        Demo::load(path).unwrap_or_else(|err| panic!("couldn't load demo {path}: {err}"))
+    });
+    let demo_path = arg_value("--record-demo").flatten();
+    let level_path = match &demo {
+        Some(demo) => demo.level.clone(),
+        None => arg_value("--level")
+            .flatten()
+            .unwrap_or("assets/levels/level1.ron")
+            .to_string(),
+    };
+    let level_path = level_path.as_str();
+    let mut recording = demo_path.map(|_| Demo::new(level_path));
+    let mut playback = demo.map(|demo| demo.frames.into_iter());
 
-        now = time::SystemTime::now();
-        game.screen.fill(0);
+    let level = load_level(level_path)
+        .unwrap_or_else(|err| panic!("couldn't load {level_path}: {err}"));
+    let mut game = match headless_frames {
+        Some(frames) => Game::headless(level.tile_map, Some(frames), script.unwrap_or_default()),
+        None => Game::windowed(level.tile_map),
+    };
+    game.controls = controls;
+    let gun_image = load_png("assets/gun.png").unwrap();
+    if let Some(music) = level.music {
+        let music = game.assets.load_sound(
+            music,
+            Some(
+                StaticSoundSettings::default()
+                    .volume(0.2)
+                    .loop_region(0.0..),
+            ),
+        );
+        game.audio.play(music.clone());
+    }
+    game.prefabs = Prefabs::load("assets/prefabs.ron").unwrap_or_else(|err| panic!("{err}"));
+    game.add_level_entities(
+        level.player_start,
+        level.look_angle,
+        &level.entities,
+        &level.prefabs,
+    )
+    .unwrap_or_else(|err| panic!("couldn't spawn the entities of {level_path}: {err}"));
 
-        let keys = game.entities.keys().copied().collect::<Vec<_>>();
+    let mut now = time::SystemTime::now();
+    let mut accumulator = 0.0;
+    // actions polled since the last tick
+    let mut pending = Actions::default();
+    loop {
+        // recordings are captured at a steady 60 fps no matter how long
+        // writing the PNGs takes
+        let frame_time = if headless_frames.is_some() || recorder.is_recording() {
+            TICK
+        } else {
+            now.elapsed().unwrap().as_secs_f32()
+        };
+        now = time::SystemTime::now();
 
-        for key in keys {
-            let mut entity = game.entities.remove(&key).unwrap();
-            entity.update(dt, &mut game);
-            if entity.alive {
-                game.entities.insert(key, entity);
-            }
+        let polled = game.controls.poll(game.platform.as_mut());
+        if !game.platform.is_open() || polled.is_down(Action::Quit) {
+            break;
         }
-        game.tile_map.update(dt);
-        game.renderer.render(&mut game.screen, &mut game.assets);
+        let alpha = match &mut playback {
+            // demos hold one tick per frame
+            Some(frames) => {
+                let Some(frame) = frames.next() else {
+                    break;
+                };
+                game.actions = frame.actions;
+                game.tick(frame.dt);
+                1.0
+            }
+            None => {
+                pending.merge(&polled);
+                accumulator += frame_time;
+                let mut ticks = 0;
+                while accumulator >= TICK {
+                    if ticks == MAX_TICKS_PER_FRAME {
+                        accumulator = 0.0;
+                        break;
+                    }
+                    game.actions = pending.take();
+                    if let Some(demo) = &mut recording {
+                        demo.frames.push(DemoFrame {
+                            dt: TICK,
+                            actions: game.actions.clone(),
+                        });
+                    }
+                    game.tick(TICK);
+                    accumulator -= TICK;
+                    ticks += 1;
+                }
+                accumulator / TICK
+            }
+        };
+        game.render(alpha);
 
         let surf_to_blit = &gun_image;
         game.screen.blit_scaled(
@@ -239,9 +506,22 @@ fn main() {
             6.0,
         );
 
-        game.window
-            .update_with_buffer(&game.screen.pixel_buffer, WIDTH, HEIGHT)
-            .unwrap();
+        if polled.is_pressed(Action::Screenshot) {
+            save_screenshot(&game.screen);
+        }
+        if polled.is_pressed(Action::Record) && !recorder.is_recording() {
+            recorder.start();
+        }
+        recorder.capture(&game.screen);
+
+        game.platform.present(&game.screen);
+    }
+
+    if let (Some(demo), Some(path)) = (recording, demo_path) {
+        match demo.save(path) {
+            Ok(()) => log::info!("Saved demo to {path}"),
+            Err(err) => log::error!("Couldn't save demo to {path}, ERROR: {err}"),
+        }
     }
 }
 
@@ -1,16 +1,20 @@
 
 use crate::drawing::val_from_rgb;
+use glam::Vec3;
 
 
 
+/// Scales every channel of `val` by the matching channel of `factor`, 1.0
+/// keeps a channel as is. Channels are clamped at full brightness.
 #[inline]
-pub fn set_value_brightness(val: u32, brightness: u32) -> u32 {
+pub fn set_value_color(val: u32, factor: Vec3) -> u32 {
+    let factor = (factor.max(Vec3::ZERO) * 255.0).as_uvec3();
     let b = val & 0xFF;
     let g = val >> 8 & 0xFF;
     let r = val >> 16 & 0xFF;
     val_from_rgb(
-        (r * brightness) / 255,
-        (g * brightness) / 255,
-        (b * brightness) / 255,
+        (r * factor.x) / 255,
+        (g * factor.y) / 255,
+        (b * factor.z) / 255,
     )
 }
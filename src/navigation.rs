@@ -0,0 +1,266 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use glam::*;
+
+use crate::tile_map::{TileMap, TileType, HEAD_HEIGHT};
+
+/// Half the width of the widest entity that follows paths. Smoothed paths
+/// keep at least this far from blocked cells.
+pub const CLEARANCE: f32 = 0.3;
+/// How far a door has to be open before entities can get through it.
+pub const DOOR_OPEN: f32 = 0.5;
+/// Paths cached at most before the cache is cleared.
+const MAX_CACHED_PATHS: usize = 1024;
+
+/// The cells of a `TileMap` entities can walk through and paths between
+/// them. Kept up to date with opening doors by `update`.
+pub struct Navigation {
+    width: usize,
+    height: usize,
+    walkable: Vec<bool>,
+    /// Changes whenever a cell becomes walkable or blocked, paths found
+    /// before are stale.
+    pub version: u32,
+    cache: HashMap<(IVec2, IVec2), Option<Vec<Vec2>>>,
+}
+impl Navigation {
+    pub fn new(tile_map: &TileMap) -> Self {
+        Navigation {
+            width: tile_map.width,
+            height: tile_map.height,
+            walkable: walkable_cells(tile_map),
+            version: 0,
+            cache: HashMap::new(),
+        }
+    }
+    /// Rechecks the cells in `tile_map.tile_update_indeces`, the doors that
+    /// are opening, and drops the cached paths if any of them changed.
+    pub fn update(&mut self, tile_map: &TileMap) {
+        let mut changed = false;
+        for &index in &tile_map.tile_update_indeces {
+            let cell = ivec2((index % self.width) as i32, (index / self.width) as i32);
+            let walkable = is_walkable(tile_map, cell);
+            if self.walkable[index] != walkable {
+                self.walkable[index] = walkable;
+                changed = true;
+            }
+        }
+        if changed {
+            self.version = self.version.wrapping_add(1);
+            self.cache.clear();
+        }
+    }
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        0 <= cell.x
+            && cell.x < self.width as i32
+            && 0 <= cell.y
+            && cell.y < self.height as i32
+            && self.walkable[cell.x as usize + cell.y as usize * self.width]
+    }
+    /// Waypoints from `from` to the cell of `to`, ending in the centre of
+    /// that cell. The cell `from` is in is left out, so an entity that is
+    /// already in the cell of `to` gets no waypoints. `None` if the cell of
+    /// `to` can't be reached.
+    pub fn find_path(&mut self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let key = (from.floor().as_ivec2(), to.floor().as_ivec2());
+        if let Some(path) = self.cache.get(&key) {
+            return path.clone();
+        }
+        if self.cache.len() >= MAX_CACHED_PATHS {
+            self.cache.clear();
+        }
+        let path = self.a_star(key.0, key.1).map(|cells| self.smooth(&cells));
+        self.cache.insert(key, path.clone());
+        path
+    }
+
+    /// Shortest path of cells from `start` to `goal`, both included.
+    /// Diagonal steps can't cut past a blocked corner.
+    fn a_star(&self, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+        if !self.is_walkable(goal) || !self.in_bounds(start) {
+            return None;
+        }
+        let index = |cell: IVec2| cell.x as usize + cell.y as usize * self.width;
+        let cell = |index: usize| ivec2((index % self.width) as i32, (index / self.width) as i32);
+        // octile distance in tenths of a tile
+        let heuristic = |cell: IVec2| {
+            let delta = (goal - cell).abs();
+            10 * delta.max_element() as u32 + 4 * delta.min_element() as u32
+        };
+
+        let mut cost = vec![u32::MAX; self.walkable.len()];
+        let mut came_from = vec![usize::MAX; self.walkable.len()];
+        // ties go to the cell closer to the goal, then to the lower index,
+        // so the same query always finds the same path
+        let mut open =
+            BinaryHeap::from([Reverse((heuristic(start), heuristic(start), index(start)))]);
+        cost[index(start)] = 0;
+        while let Some(Reverse((_, _, current))) = open.pop() {
+            let pos = cell(current);
+            if pos == goal {
+                let mut path = vec![pos];
+                let mut current = current;
+                while came_from[current] != usize::MAX {
+                    current = came_from[current];
+                    path.push(cell(current));
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for step in [
+                ivec2(1, 0),
+                ivec2(-1, 0),
+                ivec2(0, 1),
+                ivec2(0, -1),
+                ivec2(1, 1),
+                ivec2(-1, 1),
+                ivec2(1, -1),
+                ivec2(-1, -1),
+            ] {
+                let next = pos + step;
+                let diagonal = step.x != 0 && step.y != 0;
+                if !self.is_walkable(next)
+                    || diagonal
+                        && (!self.is_walkable(pos + ivec2(step.x, 0))
+                            || !self.is_walkable(pos + ivec2(0, step.y)))
+                {
+                    continue;
+                }
+                let next_cost = cost[current] + if diagonal { 14 } else { 10 };
+                if next_cost < cost[index(next)] {
+                    cost[index(next)] = next_cost;
+                    came_from[index(next)] = current;
+                    let h = heuristic(next);
+                    open.push(Reverse((next_cost + h, h, index(next))));
+                }
+            }
+        }
+        None
+    }
+
+    /// Drops every cell that can be skipped by walking straight to a later
+    /// one, leaving the centres of the corners of the path.
+    fn smooth(&self, cells: &[IVec2]) -> Vec<Vec2> {
+        let centre = |cell: IVec2| cell.as_vec2() + vec2(0.5, 0.5);
+        let mut waypoints = Vec::new();
+        let mut anchor = 0;
+        while anchor + 1 < cells.len() {
+            let next = (anchor + 2..cells.len())
+                .rev()
+                .find(|&i| self.is_clear(centre(cells[anchor]), centre(cells[i])))
+                .unwrap_or(anchor + 1);
+            waypoints.push(centre(cells[next]));
+            anchor = next;
+        }
+        waypoints
+    }
+
+    /// Whether an entity `2 * CLEARANCE` wide can walk straight from `from`
+    /// to `to` without touching a blocked cell.
+    pub fn is_clear(&self, from: Vec2, to: Vec2) -> bool {
+        let steps = (from.distance(to) / 0.1).ceil().max(1.0) as usize;
+        (0..=steps).all(|step| {
+            let pos = from.lerp(to, step as f32 / steps as f32);
+            [
+                vec2(CLEARANCE, CLEARANCE),
+                vec2(-CLEARANCE, CLEARANCE),
+                vec2(CLEARANCE, -CLEARANCE),
+                vec2(-CLEARANCE, -CLEARANCE),
+            ]
+            .iter()
+            .all(|&corner| self.is_walkable((pos + corner).floor().as_ivec2()))
+        })
+    }
+
+    fn in_bounds(&self, cell: IVec2) -> bool {
+        0 <= cell.x && cell.x < self.width as i32 && 0 <= cell.y && cell.y < self.height as i32
+    }
+}
+
+fn walkable_cells(tile_map: &TileMap) -> Vec<bool> {
+    let mut walkable = Vec::with_capacity(tile_map.width * tile_map.height);
+    for y in 0..tile_map.height as i32 {
+        for x in 0..tile_map.width as i32 {
+            walkable.push(is_walkable(tile_map, ivec2(x, y)));
+        }
+    }
+    walkable
+}
+
+/// Empty cells, passable tiles, tiles above head height and doors that are
+/// open far enough. Subwalls block the whole cell, there is no room to walk
+/// past them.
+fn is_walkable(tile_map: &TileMap, cell: IVec2) -> bool {
+    match tile_map.get_tile(cell) {
+        None => true,
+        Some(tile) => {
+            tile.passable
+                || tile.elevation >= HEAD_HEIGHT
+                || matches!(tile.tile_type, TileType::Door(opened, _) if opened >= DOOR_OPEN)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map::test_map;
+
+    #[test]
+    fn goes_around_walls() {
+        let tile_map = test_map(&[
+            "11111",
+            "1   1",
+            "1 1 1",
+            "1 1 1",
+            "11111",
+        ]);
+        let mut navigation = Navigation::new(&tile_map);
+        let path = navigation
+            .find_path(vec2(1.5, 3.5), vec2(3.5, 3.5))
+            .unwrap();
+        assert_eq!(path, [vec2(1.5, 1.5), vec2(3.5, 1.5), vec2(3.5, 3.5)]);
+    }
+
+    #[test]
+    fn smooths_open_rooms_into_a_straight_line() {
+        let tile_map = test_map(&[
+            "111111",
+            "1    1",
+            "1    1",
+            "1    1",
+            "111111",
+        ]);
+        let mut navigation = Navigation::new(&tile_map);
+        let path = navigation
+            .find_path(vec2(1.5, 1.5), vec2(4.5, 3.5))
+            .unwrap();
+        assert_eq!(path, [vec2(4.5, 3.5)]);
+    }
+
+    #[test]
+    fn doors_and_subwalls_block_until_opened() {
+        let mut tile_map = test_map(&[
+            "11111",
+            "1 1 1",
+            "1 = 1",
+            "1 -11",
+            "11111",
+        ]);
+        let mut navigation = Navigation::new(&tile_map);
+        let (from, to) = (vec2(1.5, 1.5), vec2(3.5, 1.5));
+        assert_eq!(navigation.find_path(from, to), None);
+        navigation.update(&tile_map);
+        assert_eq!(navigation.version, 0);
+
+        tile_map.tile_update_indeces.push(2 + 2 * tile_map.width);
+        tile_map.update(2.0);
+        navigation.update(&tile_map);
+        assert_eq!(navigation.version, 1);
+        let path = navigation.find_path(from, to).unwrap();
+        assert_eq!(path, [vec2(1.5, 2.5), vec2(3.5, 2.5), vec2(3.5, 1.5)]);
+    }
+}
@@ -0,0 +1,356 @@
+use std::{collections::VecDeque, error::Error, fs};
+
+use glam::*;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use serde::Deserialize;
+
+use crate::{
+    controls::{Binding, BindingDef},
+    surface::Surface,
+};
+
+/// Everything the game needs from the host: somewhere to show `Game::screen`
+/// and a source of keyboard and mouse input.
+pub trait Platform {
+    fn is_open(&self) -> bool;
+    fn present(&mut self, screen: &Surface);
+    fn get_keys(&self) -> Vec<Key>;
+    fn get_keys_pressed(&self) -> Vec<Key>;
+    /// How far the mouse moved during the last frame, in window pixels.
+    fn get_mouse_delta(&self) -> Vec2;
+    fn get_mouse_down(&self) -> Vec<MouseButton>;
+    fn get_mouse_pressed(&self) -> Vec<MouseButton>;
+    /// A captured mouse is hidden and only used for looking around.
+    fn is_mouse_captured(&self) -> bool;
+    fn set_mouse_captured(&mut self, captured: bool);
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.get_keys().contains(&key)
+    }
+    fn is_mouse_down(&self, button: MouseButton) -> bool {
+        self.get_mouse_down().contains(&button)
+    }
+}
+
+const MOUSE_BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Middle, MouseButton::Right];
+
+/// minifb can't warp or lock the cursor, so a captured mouse is just hidden
+/// and turning stops once the cursor leaves the screen.
+pub struct MinifbPlatform {
+    window: Window,
+    mouse_pos: Option<Vec2>,
+    mouse_delta: Vec2,
+    mouse_down: Vec<MouseButton>,
+    mouse_pressed: Vec<MouseButton>,
+    mouse_captured: bool,
+}
+impl MinifbPlatform {
+    pub fn new(title: &str, width: usize, height: usize) -> Self {
+        let mut window = Window::new(
+            title,
+            width,
+            height,
+            WindowOptions {
+                scale_mode: minifb::ScaleMode::AspectRatioStretch,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+        // Limit to max ~60 fps update rate
+        window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+        MinifbPlatform {
+            window,
+            mouse_pos: None,
+            mouse_delta: Vec2::ZERO,
+            mouse_down: Vec::new(),
+            mouse_pressed: Vec::new(),
+            mouse_captured: false,
+        }
+    }
+    /// Reads the mouse state minifb collected during the last update.
+    fn poll_mouse(&mut self) {
+        if self.mouse_captured && !self.window.is_active() {
+            self.set_mouse_captured(false);
+        }
+        let pos = self
+            .window
+            .get_unscaled_mouse_pos(MouseMode::Pass)
+            .map(Vec2::from);
+        self.mouse_delta = match (self.mouse_pos, pos) {
+            (Some(old), Some(new)) => new - old,
+            _ => Vec2::ZERO,
+        };
+        self.mouse_pos = pos;
+
+        let down: Vec<MouseButton> = MOUSE_BUTTONS
+            .into_iter()
+            .filter(|&button| self.window.get_mouse_down(button))
+            .collect();
+        self.mouse_pressed = down
+            .iter()
+            .copied()
+            .filter(|button| !self.mouse_down.contains(button))
+            .collect();
+        self.mouse_down = down;
+    }
+}
+impl Platform for MinifbPlatform {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+    fn present(&mut self, screen: &Surface) {
+        self.window
+            .update_with_buffer(&screen.pixel_buffer, screen.width, screen.height)
+            .unwrap();
+        self.poll_mouse();
+    }
+    fn get_keys(&self) -> Vec<Key> {
+        self.window.get_keys()
+    }
+    fn get_keys_pressed(&self) -> Vec<Key> {
+        self.window.get_keys_pressed(KeyRepeat::No)
+    }
+    fn is_key_down(&self, key: Key) -> bool {
+        self.window.is_key_down(key)
+    }
+    fn get_mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+    fn get_mouse_down(&self) -> Vec<MouseButton> {
+        self.mouse_down.clone()
+    }
+    fn get_mouse_pressed(&self) -> Vec<MouseButton> {
+        self.mouse_pressed.clone()
+    }
+    fn is_mouse_captured(&self) -> bool {
+        self.mouse_captured
+    }
+    fn set_mouse_captured(&mut self, captured: bool) {
+        self.mouse_captured = captured;
+        self.window.set_cursor_visibility(!captured);
+    }
+}
+
+/// Input for a single headless frame.
+#[derive(Default, Clone, Debug)]
+pub struct ScriptedInput {
+    pub keys: Vec<Key>,
+    pub keys_pressed: Vec<Key>,
+    pub mouse_delta: Vec2,
+    pub mouse_down: Vec<MouseButton>,
+    pub mouse_pressed: Vec<MouseButton>,
+}
+
+/// One step of an input script, held for `frames` frames. Bindings are
+/// written like in the controls file, `Key("W")` or `Mouse("Left")`.
+#[derive(Deserialize)]
+#[serde(default)]
+struct ScriptStep {
+    frames: usize,
+    /// Held down for the whole step.
+    down: Vec<BindingDef>,
+    /// Pressed on the first frame of the step and held down for the rest.
+    pressed: Vec<BindingDef>,
+    /// Mouse movement on every frame of the step, in window pixels.
+    mouse_delta: Vec2,
+}
+impl Default for ScriptStep {
+    fn default() -> Self {
+        ScriptStep {
+            frames: 1,
+            down: Vec::new(),
+            pressed: Vec::new(),
+            mouse_delta: Vec2::ZERO,
+        }
+    }
+}
+
+/// Reads a RON list of script steps into the input of every frame, e.g.
+/// `[(frames: 30, down: [Key("W")]), (pressed: [Mouse("Left")])]`.
+pub fn parse_input_script(contents: &str) -> Result<Vec<ScriptedInput>, Box<dyn Error>> {
+    let steps: Vec<ScriptStep> = ron::from_str(contents)?;
+    let mut frames = Vec::new();
+    for step in steps {
+        let resolve = |defs: &[BindingDef]| {
+            defs.iter()
+                .map(BindingDef::to_binding)
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let pressed = resolve(&step.pressed)?;
+        let down = [resolve(&step.down)?, pressed.clone()].concat();
+        let keys = |bindings: &[Binding]| {
+            bindings
+                .iter()
+                .filter_map(|binding| match binding {
+                    Binding::Key(key) => Some(*key),
+                    Binding::Mouse(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let buttons = |bindings: &[Binding]| {
+            bindings
+                .iter()
+                .filter_map(|binding| match binding {
+                    Binding::Mouse(button) => Some(*button),
+                    Binding::Key(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let held = ScriptedInput {
+            keys: keys(&down),
+            keys_pressed: Vec::new(),
+            mouse_delta: step.mouse_delta,
+            mouse_down: buttons(&down),
+            mouse_pressed: Vec::new(),
+        };
+        if step.frames > 0 {
+            frames.push(ScriptedInput {
+                keys_pressed: keys(&pressed),
+                mouse_pressed: buttons(&pressed),
+                ..held.clone()
+            });
+        }
+        frames.extend(std::iter::repeat_n(held, step.frames.saturating_sub(1)));
+    }
+    Ok(frames)
+}
+pub fn load_input_script(path: &str) -> Result<Vec<ScriptedInput>, Box<dyn Error>> {
+    parse_input_script(&fs::read_to_string(path)?)
+}
+
+/// A platform without a window. Frames are only ever rendered into the
+/// game's `Surface`, and input comes from a queue of scripted frames.
+pub struct HeadlessPlatform {
+    script: VecDeque<ScriptedInput>,
+    max_frames: Option<usize>,
+    pub frame_count: usize,
+    mouse_captured: bool,
+}
+impl HeadlessPlatform {
+    pub fn new(max_frames: Option<usize>) -> Self {
+        HeadlessPlatform {
+            script: VecDeque::new(),
+            max_frames,
+            frame_count: 0,
+            mouse_captured: false,
+        }
+    }
+    /// Queues input for a future frame. The front of the queue is the
+    /// current frame's input, every `present` moves on to the next one.
+    pub fn push_input(&mut self, input: ScriptedInput) {
+        self.script.push_back(input);
+    }
+}
+impl Platform for HeadlessPlatform {
+    fn is_open(&self) -> bool {
+        self.max_frames.is_none_or(|max| self.frame_count < max)
+    }
+    fn present(&mut self, _screen: &Surface) {
+        self.frame_count += 1;
+        self.script.pop_front();
+    }
+    fn get_keys(&self) -> Vec<Key> {
+        self.script
+            .front()
+            .map(|input| input.keys.clone())
+            .unwrap_or_default()
+    }
+    fn get_keys_pressed(&self) -> Vec<Key> {
+        self.script
+            .front()
+            .map(|input| input.keys_pressed.clone())
+            .unwrap_or_default()
+    }
+    fn get_mouse_delta(&self) -> Vec2 {
+        self.script
+            .front()
+            .map_or(Vec2::ZERO, |input| input.mouse_delta)
+    }
+    fn get_mouse_down(&self) -> Vec<MouseButton> {
+        self.script
+            .front()
+            .map(|input| input.mouse_down.clone())
+            .unwrap_or_default()
+    }
+    fn get_mouse_pressed(&self) -> Vec<MouseButton> {
+        self.script
+            .front()
+            .map(|input| input.mouse_pressed.clone())
+            .unwrap_or_default()
+    }
+    fn is_mouse_captured(&self) -> bool {
+        self.mouse_captured
+    }
+    fn set_mouse_captured(&mut self, captured: bool) {
+        self.mouse_captured = captured;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::{BasicCollisionComponent, PlayerInputComponent},
+        entity::Entity,
+        tile_map::test_map,
+        Audio, Game, TICK,
+    };
+
+    #[test]
+    fn drives_the_player_with_scripted_input() {
+        let script = parse_input_script(
+            r#"[
+                (frames: 20, down: [Key("W")]),
+                // the first click only captures the mouse, it doesn't fire
+                (pressed: [Mouse("Left")]),
+                (frames: 19, mouse_delta: (10.0, 0.0)),
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(script.len(), 40);
+        let mut platform = HeadlessPlatform::new(Some(40));
+        for input in script {
+            platform.push_input(input);
+        }
+        let tile_map = test_map(&[
+            "111",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "1 1",
+            "111",
+        ]);
+        let mut game = Game::new(Box::new(platform), Audio::silent(), tile_map, 16, 16);
+        let player = game.entities.add(Entity::new(
+            vec2(1.5, 5.5),
+            None,
+            Vec2::ZERO,
+            0.6,
+            true,
+            vec![Box::new(BasicCollisionComponent), Box::new(PlayerInputComponent)],
+        ));
+
+        let mut frames = 0;
+        while game.platform.is_open() {
+            game.actions = game.controls.poll(game.platform.as_mut());
+            game.tick(TICK);
+            game.platform.present(&game.screen);
+            frames += 1;
+        }
+        assert_eq!(frames, 40);
+        assert_eq!(game.entities.ids(), [player]);
+        let player = game.entities.get(player).unwrap();
+        assert!(player.rect.pos.y < 4.6);
+        let turned = 19.0 * 10.0 * game.controls.mouse.sensitivity;
+        assert!((player.look_angle - turned).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_scripts_with_unknown_bindings() {
+        let err = parse_input_script(r#"[(down: [Key("Jump")])]"#).unwrap_err();
+        assert_eq!(err.to_string(), r#"unknown key or button "Jump""#);
+    }
+}
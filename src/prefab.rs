@@ -0,0 +1,125 @@
+use std::{collections::HashMap, error::Error, fmt, fs, io};
+
+use glam::*;
+use serde::Deserialize;
+
+use crate::{
+    entity::Entity,
+    level::{leak, ComponentDef},
+    lighting::LightSource,
+};
+
+/// A named kind of entity, see `assets/prefabs.ron`.
+#[derive(Deserialize)]
+pub struct Prefab {
+    pub sprite: Option<String>,
+    pub size: f32,
+    #[serde(default)]
+    pub collidable: bool,
+    #[serde(default)]
+    pub components: Vec<ComponentDef>,
+    #[serde(default)]
+    pub light: Option<LightSource>,
+}
+impl Prefab {
+    pub fn build(&self, pos: Vec2) -> Entity<'static> {
+        let mut entity = Entity::new(
+            pos,
+            self.sprite.as_deref().map(leak),
+            Vec2::ZERO,
+            self.size,
+            self.collidable,
+            self.components.iter().map(ComponentDef::build).collect(),
+        );
+        entity.light = self.light;
+        entity
+    }
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    Parse {
+        path: String,
+        source: ron::error::SpannedError,
+    },
+    Unknown(String),
+}
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::Io { path, source } => write!(f, "couldn't read {path}: {source}"),
+            PrefabError::Parse { path, source } => write!(f, "couldn't parse {path}: {source}"),
+            PrefabError::Unknown(name) => write!(f, "unknown prefab {name:?}"),
+        }
+    }
+}
+impl Error for PrefabError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrefabError::Io { source, .. } => Some(source),
+            PrefabError::Parse { source, .. } => Some(source),
+            PrefabError::Unknown(_) => None,
+        }
+    }
+}
+
+/// Every prefab of the game by name.
+#[derive(Default)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+}
+impl Prefabs {
+    pub fn load(path: &str) -> Result<Self, PrefabError> {
+        let contents = fs::read_to_string(path).map_err(|source| PrefabError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        let prefabs = ron::from_str(&contents).map_err(|source| PrefabError::Parse {
+            path: path.to_string(),
+            source,
+        })?;
+        Ok(Prefabs { prefabs })
+    }
+    pub fn get(&self, name: &str) -> Result<&Prefab, PrefabError> {
+        self.prefabs
+            .get(name)
+            .ok_or_else(|| PrefabError::Unknown(name.to_string()))
+    }
+    /// Builds the prefab `name` centred on `pos`.
+    pub fn build(&self, name: &str, pos: Vec2) -> Result<Entity<'static>, PrefabError> {
+        Ok(self.get(name)?.build(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{level::PrefabSpawn, tile_map::SpawnKind};
+
+    #[test]
+    fn builds_every_prefab_the_game_spawns() {
+        let prefabs = Prefabs::load("assets/prefabs.ron").unwrap();
+        let markers = [SpawnKind::Player, SpawnKind::Enemy, SpawnKind::Key]
+            .into_iter()
+            .filter_map(|kind| PrefabSpawn::from_marker(kind, Vec2::ZERO))
+            .map(|spawn| spawn.prefab);
+        for name in markers.chain(["player".to_string(), "explosion".to_string()]) {
+            if let Err(err) = prefabs.build(&name, vec2(1.5, 2.5)) {
+                panic!("couldn't build {name}: {err}");
+            }
+        }
+    }
+
+    #[test]
+    fn reports_unknown_prefabs() {
+        let prefabs = Prefabs::load("assets/prefabs.ron").unwrap();
+        assert!(matches!(
+            prefabs.build("dragon", Vec2::ZERO),
+            Err(PrefabError::Unknown(name)) if name == "dragon"
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+use glam::*;
+
+use crate::{
+    depth_buffer::Direction,
+    entity::Entities,
+    rect::Rect,
+    tile_map::{Tile, TileMap, TileType, HEAD_HEIGHT},
+};
+
+/// What a ray hit first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayTarget {
+    /// The tile in this cell.
+    Tile(IVec2),
+    Entity(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub target: RayTarget,
+    pub point: Vec2,
+    /// From the start of the ray to `point`, in tiles.
+    pub distance: f32,
+    /// The side that was hit, as the direction it faces. Doors and subwalls
+    /// face the side the ray came from.
+    pub face: IVec2,
+}
+
+/// Casts a ray from `origin` along `dir` and returns the first tile or
+/// collidable entity within `max_distance` that it hits, skipping the entity
+/// `ignore`. Tiles block rays like they block projectiles, see
+/// `cast_tiles`.
+pub fn cast_ray(
+    tile_map: &TileMap,
+    entities: &Entities,
+    origin: Vec2,
+    dir: Vec2,
+    max_distance: f32,
+    ignore: Option<u32>,
+) -> Option<RayHit> {
+    let dir = dir.normalize();
+    let tile_hit = cast_tiles(tile_map, origin, dir, max_distance);
+    let max_distance = tile_hit.map_or(max_distance, |hit| hit.distance);
+    entities
+        .iter()
+        .filter(|entity| entity.collidable && Some(entity.id) != ignore)
+        .filter_map(|entity| {
+            let (distance, face) = hit_rect(&entity.rect, origin, dir)?;
+            (distance <= max_distance).then_some(RayHit {
+                target: RayTarget::Entity(entity.id),
+                point: origin + dir * distance,
+                distance,
+                face,
+            })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+        .or(tile_hit)
+}
+
+/// Marches a ray through the map like `CameraComponent` does, stopping at the
+/// first tile that isn't `projectile_passable` and reaches below head
+/// height. Doors and subwalls are only hit where they are drawn: on the plane
+/// `offset` into their cell, and for doors only on the part that isn't open
+/// yet.
+pub fn cast_tiles(
+    tile_map: &TileMap,
+    origin: Vec2,
+    dir: Vec2,
+    max_distance: f32,
+) -> Option<RayHit> {
+    march(tile_map, origin, dir, max_distance, |tile| {
+        !tile.projectile_passable && tile.elevation < HEAD_HEIGHT
+    })
+}
+
+/// Like `cast_tiles`, but stops at the first tile that hides what is behind
+/// it at head height. Subwalls are see-through like the camera draws them.
+pub fn cast_sight(
+    tile_map: &TileMap,
+    origin: Vec2,
+    dir: Vec2,
+    max_distance: f32,
+) -> Option<RayHit> {
+    march(tile_map, origin, dir, max_distance, |tile| {
+        !matches!(tile.tile_type, TileType::Subwall(..)) && tile.elevation < HEAD_HEIGHT
+    })
+}
+
+/// Marches a ray through the map, stopping at the first tile `blocks` lets
+/// it hit.
+fn march(
+    tile_map: &TileMap,
+    origin: Vec2,
+    dir: Vec2,
+    max_distance: f32,
+    blocks: impl Fn(&Tile) -> bool,
+) -> Option<RayHit> {
+    let dir = dir.normalize();
+    let unit_step = vec2((1.0 / dir.x).abs(), (1.0 / dir.y).abs());
+    let mut cell = origin.floor().as_ivec2();
+    let step = ivec2(
+        if dir.x < 0.0 { -1 } else { 1 },
+        if dir.y < 0.0 { -1 } else { 1 },
+    );
+    // distance along the ray to the next vertical and horizontal grid line
+    let mut next = vec2(
+        if dir.x < 0.0 {
+            origin.x - cell.x as f32
+        } else {
+            cell.x as f32 + 1.0 - origin.x
+        } * unit_step.x,
+        if dir.y < 0.0 {
+            origin.y - cell.y as f32
+        } else {
+            cell.y as f32 + 1.0 - origin.y
+        } * unit_step.y,
+    );
+
+    loop {
+        let (direction, distance) = if next.x < next.y {
+            cell.x += step.x;
+            next.x += unit_step.x;
+            (Direction::Vertical, next.x - unit_step.x)
+        } else {
+            cell.y += step.y;
+            next.y += unit_step.y;
+            (Direction::Horizontal, next.y - unit_step.y)
+        };
+        if distance > max_distance || !in_bounds(tile_map, cell) {
+            return None;
+        }
+        let Some(tile) = tile_map.get_tile(cell).filter(|tile| blocks(tile)) else {
+            continue;
+        };
+        let face = match direction {
+            Direction::Horizontal => ivec2(0, -step.y),
+            Direction::Vertical => ivec2(-step.x, 0),
+        };
+        let axis_step = match direction {
+            Direction::Horizontal => unit_step.y,
+            Direction::Vertical => unit_step.x,
+        };
+
+        let (offset, open) = match tile.tile_type {
+            TileType::Wall => (0.0, 0.0),
+            TileType::Door(open, door_direction) if door_direction == direction => (0.5, open),
+            TileType::Subwall(offset, wall_direction) if wall_direction == direction => {
+                (offset, 0.0)
+            }
+            // doors and subwalls are thin, rays entering from the side
+            // pass them
+            _ => continue,
+        };
+        let distance = distance + offset * axis_step;
+        let point = origin + dir * distance;
+        // the ray can leave the cell through a side before reaching the
+        // plane of a door or subwall
+        if offset > 0.0 && point.floor().as_ivec2() != cell {
+            continue;
+        }
+        let along = match direction {
+            Direction::Horizontal => point.x.fract(),
+            Direction::Vertical => point.y.fract(),
+        };
+        if along > 1.0 - open {
+            continue;
+        }
+        if distance > max_distance {
+            return None;
+        }
+        return Some(RayHit {
+            target: RayTarget::Tile(cell),
+            point,
+            distance,
+            face,
+        });
+    }
+}
+
+fn in_bounds(tile_map: &TileMap, cell: IVec2) -> bool {
+    0 <= cell.x && cell.x < tile_map.width as i32 && 0 <= cell.y && cell.y < tile_map.height as i32
+}
+
+/// Distance along the normalized `dir` to where the ray enters `rect`, and
+/// the side it enters through. `None` if it misses or starts inside.
+fn hit_rect(rect: &Rect, origin: Vec2, dir: Vec2) -> Option<(f32, IVec2)> {
+    let min = vec2(rect.get_left(), rect.get_top());
+    let max = vec2(rect.get_right(), rect.get_bottom());
+    let to_min = (min - origin) / dir;
+    let to_max = (max - origin) / dir;
+    let near = to_min.min(to_max);
+    let far = to_min.max(to_max);
+    let enter = near.max_element();
+    if enter < 0.0 || enter > far.min_element() {
+        return None;
+    }
+    let face = if near.x > near.y {
+        ivec2(-dir.x.signum() as i32, 0)
+    } else {
+        ivec2(0, -dir.y.signum() as i32)
+    };
+    Some((enter, face))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entity::Entity,
+        tile_map::test_map,
+    };
+
+    #[test]
+    fn hits_the_facing_side_of_a_wall() {
+        let tile_map = test_map(&[
+            "11111",
+            "1   1",
+            "11111",
+        ]);
+        let hit = cast_tiles(&tile_map, vec2(1.5, 1.5), vec2(1.0, 0.0), 10.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Tile(ivec2(4, 1)));
+        assert_eq!(hit.point, vec2(4.0, 1.5));
+        assert_eq!(hit.distance, 2.5);
+        assert_eq!(hit.face, ivec2(-1, 0));
+        assert_eq!(
+            cast_tiles(&tile_map, vec2(1.5, 1.5), vec2(1.0, 0.0), 2.0),
+            None
+        );
+    }
+
+    #[test]
+    fn respects_door_opening_and_subwall_offsets() {
+        let mut tile_map = test_map(&[
+            "111",
+            "1 1",
+            "1=1",
+            "1 1",
+            "1-1",
+            "1 1",
+            "111",
+        ]);
+        let origin = vec2(1.2, 1.5);
+        let down = vec2(0.0, 1.0);
+        let hit = cast_tiles(&tile_map, origin, down, 10.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Tile(ivec2(1, 2)));
+        assert_eq!(hit.point, vec2(1.2, 2.5));
+        assert_eq!(hit.face, ivec2(0, -1));
+
+        // the door slides towards -x, opening up its +x end first
+        tile_map.tile_update_indeces.push(1 + 2 * tile_map.width);
+        tile_map.update(1.5);
+        let hit = cast_tiles(&tile_map, origin, down, 10.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Tile(ivec2(1, 2)));
+        let hit = cast_tiles(&tile_map, vec2(1.7, 1.5), down, 10.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Tile(ivec2(1, 4)));
+        assert_eq!(hit.point, vec2(1.7, 4.5));
+    }
+
+    #[test]
+    fn sight_passes_subwalls_but_not_doors() {
+        let tile_map = test_map(&[
+            "111",
+            "1 1",
+            "1-1",
+            "1 1",
+            "1=1",
+            "1 1",
+            "111",
+        ]);
+        let origin = vec2(1.5, 1.5);
+        let down = vec2(0.0, 1.0);
+        let hit = cast_tiles(&tile_map, origin, down, 10.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Tile(ivec2(1, 2)));
+        let hit = cast_sight(&tile_map, origin, down, 10.0).unwrap();
+        assert_eq!(hit.target, RayTarget::Tile(ivec2(1, 4)));
+        assert_eq!(cast_sight(&tile_map, origin, down, 2.5), None);
+    }
+
+    #[test]
+    fn hits_the_nearest_entity_in_front_of_walls() {
+        let tile_map = test_map(&[
+            "111111",
+            "1    1",
+            "111111",
+        ]);
+        let mut entities = Entities::default();
+        let entity = |x| Entity::new(vec2(x, 1.5), None, Vec2::ZERO, 0.6, true, vec![]);
+        let shooter = entities.add(entity(1.5));
+        let near = entities.add(entity(3.0));
+        entities.add(entity(4.0));
+
+        let hit = cast_ray(
+            &tile_map,
+            &entities,
+            vec2(1.5, 1.5),
+            vec2(1.0, 0.0),
+            10.0,
+            Some(shooter),
+        );
+        let hit = hit.unwrap();
+        assert_eq!(hit.target, RayTarget::Entity(near));
+        assert!((hit.distance - 1.2).abs() < 1e-5);
+        assert_eq!(hit.face, ivec2(-1, 0));
+    }
+}
@@ -28,7 +28,7 @@ impl Surface {
             self.pixel_buffer[index] = value;
             return Ok(());
         }
-        return Err(());
+        Err(())
     }
 
     pub fn blit(&mut self, source: &Surface, x: i32, y: i32) {
@@ -45,18 +45,33 @@ impl Surface {
         }
     }
     pub fn blit_scaled(&mut self, source: &Surface, pos: IVec2, scale: f32) {
+        self.blit_scaled_columns(source, pos, scale, |_| true, |val| val);
+    }
+    /// Like `blit_scaled`, but only draws the columns of `self` for which
+    /// `visible` returns true and passes every drawn pixel through `shade`.
+    pub fn blit_scaled_columns(
+        &mut self,
+        source: &Surface,
+        pos: IVec2,
+        scale: f32,
+        visible: impl Fn(usize) -> bool,
+        shade: impl Fn(u32) -> u32,
+    ) {
         let scaled_width = (source.width as f32 * scale) as i32;
         let scaled_height = (source.height as f32 * scale) as i32;
         let offset_x = pos.x - scaled_width / 2;
         let offset_y = pos.y - scaled_height / 2;
+        let columns: Vec<i32> = (offset_x.max(0)..(offset_x + scaled_width).min(self.width as i32))
+            .filter(|&x| visible(x as usize))
+            .collect();
         for y in offset_y.max(0)..(offset_y + scaled_height).min(self.height as i32) {
-            for x in offset_x.max(0)..(offset_x + scaled_width).min(self.width as i32) {
+            for &x in &columns {
                 let index_self = x as usize + y as usize * self.width;
                 let index_source = ((x - offset_x) as f32 / scale) as usize
                     + ((y - offset_y) as f32 / scale) as usize * source.width;
                 let val = source.pixel_buffer[index_source];
                 if val != 0 {
-                    self.pixel_buffer[index_self] = val;
+                    self.pixel_buffer[index_self] = shade(val);
                 }
             }
         }
@@ -1,12 +1,31 @@
-use std::{error::Error, fs};
+use std::{collections::HashMap, fs, sync::Arc};
 
-use crate::{depth_buffer::Direction, rect::Rect};
+use crate::{
+    depth_buffer::Direction,
+    fog::Fog,
+    lighting::LightMap,
+    rect::Rect,
+    validation::{Diagnostic, DiagnosticKind, MapError},
+};
 use glam::*;
 pub struct TileMap<'a> {
     pub width: usize,
     pub height: usize,
     buf: Vec<Option<Tile<'a>>>,
     pub tile_update_indeces: Vec<usize>,
+    /// Floor and ceiling of cells that don't set their own.
+    pub floor_texture: &'a str,
+    pub ceiling_texture: &'a str,
+    surfaces: Vec<Option<Surfaces<'a>>>,
+    /// Panorama drawn where there is no ceiling, wrapping once around the
+    /// camera. Without one the sky is the fog colour.
+    pub sky_texture: Option<&'a str>,
+    /// Spawn markers found in the grid, in reading order.
+    pub spawns: Vec<Spawn>,
+    pub light_map: Arc<LightMap>,
+    pub fog: Fog,
+    /// See `max_top`. Tile heights are fixed once the map is parsed.
+    max_top: f32,
 }
 impl<'a> TileMap<'a> {
     pub fn get_tile(&self, pos: IVec2) -> Option<&Tile<'a>> {
@@ -21,6 +40,72 @@ impl<'a> TileMap<'a> {
         }
         None
     }
+    /// Floor and ceiling of the cell at `pos`, the map defaults outside of
+    /// the map.
+    pub fn get_surfaces(&self, pos: IVec2) -> Surfaces<'a> {
+        let in_bounds =
+            0 <= pos.x && pos.x < self.width as i32 && 0 <= pos.y && pos.y < self.height as i32;
+        let own = if in_bounds {
+            self.surfaces[pos.x as usize + pos.y as usize * self.width]
+        } else {
+            None
+        };
+        own.unwrap_or(Surfaces {
+            floor: self.floor_texture,
+            ceiling: Some(self.ceiling_texture),
+        })
+    }
+    /// Lays a grid of surface glyphs over the map, it has to be as big as
+    /// the map. Spaces keep the map defaults, any other glyph has to be in
+    /// `legend`.
+    pub fn parse_surfaces(
+        &mut self,
+        lines: &[&str],
+        legend: &HashMap<char, Surfaces<'a>>,
+    ) -> Result<(), MapError> {
+        let mut diagnostics = Vec::new();
+        if lines.len() != self.height {
+            diagnostics.push(Diagnostic {
+                row: lines.len().min(self.height),
+                col: 0,
+                kind: DiagnosticKind::WrongRowCount {
+                    expected: self.height,
+                    found: lines.len(),
+                },
+            });
+        }
+        for (y, line) in lines.iter().take(self.height).enumerate() {
+            let found = line.chars().count();
+            if found != self.width {
+                diagnostics.push(Diagnostic {
+                    row: y,
+                    col: found.min(self.width),
+                    kind: DiagnosticKind::RaggedRow {
+                        expected: self.width,
+                        found,
+                    },
+                });
+            }
+            for (x, c) in line.chars().take(self.width).enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                match legend.get(&c) {
+                    Some(&surfaces) => self.surfaces[x + y * self.width] = Some(surfaces),
+                    None => diagnostics.push(Diagnostic {
+                        row: y,
+                        col: x,
+                        kind: DiagnosticKind::UnknownGlyph(c),
+                    }),
+                }
+            }
+        }
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(MapError::Invalid(diagnostics))
+        }
+    }
     pub fn update(&mut self, dt: f32) {
         let mut new_indeces=  Vec::new();
         for &index in &self.tile_update_indeces {
@@ -37,11 +122,19 @@ impl<'a> TileMap<'a> {
         }
         self.tile_update_indeces = new_indeces;
     }
+    /// Top of the tallest tile, the highest anything behind a wall can reach.
+    pub fn max_top(&self) -> f32 {
+        self.max_top
+    }
     pub fn get_collisions(&'a self, rect: &'a Rect) -> Vec<Rect> {
         rect.get_corners()
             .iter()
             .filter_map(|pos| {
-                if let Some(tile) = self.get_tile(pos.as_ivec2()) {
+                // overhangs above head height can be walked under
+                if let Some(tile) = self
+                    .get_tile(pos.as_ivec2())
+                    .filter(|tile| !tile.passable && tile.elevation < HEAD_HEIGHT)
+                {
                     let pos = pos.floor() + vec2(0.5, 0.5);
                     let tile_rect = match tile.tile_type {
                         TileType::Wall => Rect {
@@ -83,73 +176,236 @@ impl<'a> TileMap<'a> {
     }
 }
 
+/// What is drawn below and above an empty cell, a `None` ceiling is open
+/// sky.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Surfaces<'a> {
+    pub floor: &'a str,
+    pub ceiling: Option<&'a str>,
+}
+
+#[derive(Clone)]
 pub struct Tile<'a> {
     pub tile_type: TileType,
+    pub passable: bool,
     pub projectile_passable: bool,
     pub sprites: [&'a str; 2],
+    /// Height of the bottom edge above the floor, in tiles.
+    pub elevation: f32,
+    pub height: f32,
 }
-#[derive(PartialEq)]
+impl Tile<'_> {
+    pub fn top(&self) -> f32 {
+        self.elevation + self.height
+    }
+    /// Whether the tile hides everything behind it from the floor up to its
+    /// top.
+    pub fn occludes(&self) -> bool {
+        self.tile_type == TileType::Wall && self.elevation <= 0.0
+    }
+}
+#[derive(PartialEq, Clone, Copy)]
 pub enum TileType {
     Wall,
     Subwall(f32, Direction),
     Door(f32, Direction),
 }
 
-pub fn load_map(path: &str) -> Result<TileMap, Box<dyn Error>> {
-    let contents = fs::read_to_string(path)?;
+pub const HEAD_HEIGHT: f32 = 0.75;
+pub const DEFAULT_FLOOR_TEXTURE: &str = "assets/ceil.png";
+pub const DEFAULT_CEILING_TEXTURE: &str = "assets/floor.png";
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SpawnKind {
+    Player,
+    Enemy,
+    Key,
+}
+impl SpawnKind {
+    /// Glyphs reserved for spawn markers. They take precedence over the
+    /// legend and leave an empty cell behind.
+    fn from_marker(c: char) -> Option<Self> {
+        match c {
+            'P' => Some(SpawnKind::Player),
+            'E' => Some(SpawnKind::Enemy),
+            'K' => Some(SpawnKind::Key),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug)]
+pub struct Spawn {
+    pub kind: SpawnKind,
+    /// Centre of the marked cell.
+    pub pos: Vec2,
+}
+
+/// Loads a plain ASCII map using the built-in legend.
+pub fn load_map<'a>(path: &str) -> Result<TileMap<'a>, MapError> {
+    let contents = fs::read_to_string(path).map_err(|source| MapError::Io {
+        path: path.to_string(),
+        source,
+    })?;
     let lines: Vec<&str> = contents.lines().collect();
-    let width = lines[0].len();
+    parse_grid(&lines, &default_legend())
+}
+
+/// Builds a map from rows of glyphs. Spaces are empty cells, any other glyph
+/// has to be a spawn marker or in `legend`. All rows have to be as wide as
+/// the first one.
+pub fn parse_grid<'a>(
+    lines: &[&str],
+    legend: &HashMap<char, Tile<'a>>,
+) -> Result<TileMap<'a>, MapError> {
+    let Some(first) = lines.first() else {
+        return Err(MapError::Empty);
+    };
+    let width = first.chars().count();
     let height = lines.len();
     let mut buf = Vec::with_capacity(width * height);
-    for line in lines {
-        for c in line.chars() {
-            buf.push(match c {
-                '1' => Some(Tile {
-                    tile_type: TileType::Wall,
-                    projectile_passable: false,
-                    sprites: ["assets/bricksmall.png", "assets/bricksmall2.png"],
-                }),
-                '2' => Some(Tile {
-                    tile_type: TileType::Wall,
-                    projectile_passable: false,
-                    sprites: ["assets/white.png", "assets/white.png"],
-                }),
-                '=' => Some(door(Direction::Horizontal)),
-                '/' => Some(door(Direction::Vertical)),
-                '-' => Some(subwall(Direction::Horizontal)),
-                '|' => Some(subwall(Direction::Vertical)),
-                'w' => Some(wood(Direction::Vertical)),
-                'W' => Some(wood(Direction::Horizontal)),
-                _ => None,
-            })
+    let mut spawns = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (y, line) in lines.iter().enumerate() {
+        let found = line.chars().count();
+        if found != width {
+            diagnostics.push(Diagnostic {
+                row: y,
+                col: found.min(width),
+                kind: DiagnosticKind::RaggedRow {
+                    expected: width,
+                    found,
+                },
+            });
+        }
+        // pad or cut the row so the rest of the map still lines up
+        for (x, c) in line.chars().chain(std::iter::repeat(' ')).take(width).enumerate() {
+            if let Some(kind) = SpawnKind::from_marker(c) {
+                spawns.push(Spawn {
+                    kind,
+                    pos: vec2(x as f32 + 0.5, y as f32 + 0.5),
+                });
+                buf.push(None);
+                continue;
+            }
+            let tile = legend.get(&c).cloned();
+            if tile.is_none() && c != ' ' {
+                diagnostics.push(Diagnostic {
+                    row: y,
+                    col: x,
+                    kind: DiagnosticKind::UnknownGlyph(c),
+                });
+            }
+            buf.push(tile)
         }
     }
+    if !diagnostics.is_empty() {
+        return Err(MapError::Invalid(diagnostics));
+    }
 
+    let max_top = buf.iter().flatten().map(Tile::top).fold(1.0, f32::max);
     Ok(TileMap {
         width,
         height,
         buf,
         tile_update_indeces: Vec::new(),
+        floor_texture: DEFAULT_FLOOR_TEXTURE,
+        ceiling_texture: DEFAULT_CEILING_TEXTURE,
+        surfaces: vec![None; width * height],
+        sky_texture: None,
+        spawns,
+        light_map: Arc::new(LightMap::default()),
+        fog: Fog::default(),
+        max_top,
     })
 }
+
+/// Parses `rows` with the built-in legend, for tests that need a small map.
+#[cfg(test)]
+pub fn test_map(rows: &[&str]) -> TileMap<'static> {
+    parse_grid(rows, &default_legend()).unwrap()
+}
+
+/// A headless game on a row of three open cells, for tests that don't care
+/// about the map.
+#[cfg(test)]
+pub fn test_game() -> crate::Game<'static> {
+    crate::Game::headless(test_map(&["   "]), None, Vec::new())
+}
+
+/// A small entity standing in the middle of `test_game`'s map.
+#[cfg(test)]
+pub fn test_entity(
+    components: Vec<Box<dyn crate::components::Component>>,
+) -> crate::entity::Entity<'static> {
+    crate::entity::Entity::new(vec2(1.5, 0.5), None, Vec2::ZERO, 0.5, false, components)
+}
+
+pub fn default_legend<'a>() -> HashMap<char, Tile<'a>> {
+    HashMap::from([
+        (
+            '1',
+            wall(["assets/bricksmall.png", "assets/bricksmall2.png"]),
+        ),
+        ('2', wall(["assets/white.png", "assets/white.png"])),
+        ('=', door(Direction::Horizontal)),
+        ('/', door(Direction::Vertical)),
+        ('-', subwall(Direction::Horizontal)),
+        ('|', subwall(Direction::Vertical)),
+        ('w', wood(Direction::Vertical)),
+        ('W', wood(Direction::Horizontal)),
+        (
+            '#',
+            Tile {
+                height: 2.0,
+                ..wall(["assets/bricksmall.png", "assets/bricksmall2.png"])
+            },
+        ),
+        (
+            '_',
+            Tile {
+                height: 0.5,
+                ..wall(["assets/white.png", "assets/white.png"])
+            },
+        ),
+    ])
+}
+fn wall(sprites: [&str; 2]) -> Tile<'_> {
+    Tile {
+        tile_type: TileType::Wall,
+        passable: false,
+        projectile_passable: false,
+        sprites,
+        elevation: 0.0,
+        height: 1.0,
+    }
+}
 fn door<'a>(direction: Direction) -> Tile<'a> {
     Tile {
         tile_type: TileType::Door(0.0, direction),
+        passable: false,
         projectile_passable: false,
         sprites: ["assets/door.png", "assets/door.png"],
+        elevation: 0.0,
+        height: 1.0,
     }
 }
 fn subwall<'a>(direction: Direction) -> Tile<'a> {
     Tile {
         tile_type: TileType::Subwall(0.5, direction),
+        passable: false,
         projectile_passable: false,
         sprites: ["assets/bars.png", "assets/bars.png"],
+        elevation: 0.0,
+        height: 1.0,
     }
 }
 fn wood<'a>(direction: Direction) -> Tile<'a> {
     Tile {
         tile_type: TileType::Subwall(0.3, direction),
+        passable: false,
         projectile_passable: false,
         sprites: ["assets/wood.png", "assets/wood.png"],
+        elevation: 0.0,
+        height: 1.0,
     }
 }
@@ -0,0 +1,307 @@
+use std::{collections::VecDeque, error::Error, fmt, io};
+
+use glam::*;
+
+use crate::{
+    depth_buffer::Direction,
+    level::Level,
+    tile_map::{TileMap, TileType, HEAD_HEIGHT},
+};
+
+#[derive(Debug)]
+pub enum MapError {
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    Parse {
+        path: String,
+        source: ron::error::SpannedError,
+    },
+    Empty,
+    NoPlayerStart,
+    Invalid(Vec<Diagnostic>),
+}
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io { path, source } => write!(f, "couldn't read {path}: {source}"),
+            MapError::Parse { path, source } => write!(f, "couldn't parse {path}: {source}"),
+            MapError::Empty => write!(f, "map has no rows"),
+            MapError::NoPlayerStart => write!(f, "level has no player start"),
+            MapError::Invalid(diagnostics) => {
+                write!(f, "map has {} problem(s)", diagnostics.len())?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {diagnostic}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl Error for MapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MapError::Io { source, .. } => Some(source),
+            MapError::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A problem with a single cell. `row` and `col` are zero based map
+/// coordinates, they are printed one based to match text editors.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub row: usize,
+    pub col: usize,
+    pub kind: DiagnosticKind,
+}
+#[derive(Debug, PartialEq)]
+pub enum DiagnosticKind {
+    RaggedRow { expected: usize, found: usize },
+    /// A layer over the map doesn't have as many rows as the map.
+    WrongRowCount { expected: usize, found: usize },
+    UnknownGlyph(char),
+    OpenBorder,
+    UnframedDoor,
+    UnreachableSpawn,
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.row + 1, self.col + 1)?;
+        match &self.kind {
+            DiagnosticKind::RaggedRow { expected, found } => {
+                write!(f, "row is {found} cells wide, expected {expected}")
+            }
+            DiagnosticKind::WrongRowCount { expected, found } => {
+                write!(f, "grid has {found} rows, expected {expected}")
+            }
+            DiagnosticKind::UnknownGlyph(c) => write!(f, "unknown glyph {c:?}"),
+            DiagnosticKind::OpenBorder => {
+                write!(f, "the player can walk out of the map here")
+            }
+            DiagnosticKind::UnframedDoor => write!(f, "door needs a wall on both sides"),
+            DiagnosticKind::UnreachableSpawn => {
+                write!(f, "spawn point can't be reached from the player start")
+            }
+        }
+    }
+}
+
+/// Checks a parsed level for gameplay problems: doors without a frame,
+/// walkable cells on the edge of the map the player can reach and spawns the
+/// player can't reach. Doors count as walkable since they can be opened, and
+/// so do tiles raised above head height.
+pub fn validate_level(level: &Level) -> Result<(), MapError> {
+    let tile_map = &level.tile_map;
+    let mut diagnostics = Vec::new();
+
+    for y in 0..tile_map.height as i32 {
+        for x in 0..tile_map.width as i32 {
+            if let Some(TileType::Door(_, direction)) =
+                tile_map.get_tile(ivec2(x, y)).map(|tile| tile.tile_type)
+            {
+                let side = match direction {
+                    Direction::Horizontal => ivec2(1, 0),
+                    Direction::Vertical => ivec2(0, 1),
+                };
+                let pos = ivec2(x, y);
+                if !is_frame(tile_map, pos - side) || !is_frame(tile_map, pos + side) {
+                    diagnostics.push(diagnostic(pos, DiagnosticKind::UnframedDoor));
+                }
+            }
+        }
+    }
+
+    let reachable = flood_fill(tile_map, level.player_start.as_ivec2());
+    let is_reachable = |pos: IVec2| {
+        0 <= pos.x
+            && pos.x < tile_map.width as i32
+            && 0 <= pos.y
+            && pos.y < tile_map.height as i32
+            && reachable[pos.x as usize + pos.y as usize * tile_map.width]
+    };
+    for y in 0..tile_map.height {
+        for x in 0..tile_map.width {
+            let on_border = x == 0 || y == 0 || x == tile_map.width - 1 || y == tile_map.height - 1;
+            if on_border && reachable[x + y * tile_map.width] {
+                diagnostics.push(Diagnostic {
+                    row: y,
+                    col: x,
+                    kind: DiagnosticKind::OpenBorder,
+                });
+            }
+        }
+    }
+
+    // spawn markers have already been turned into prefab spawns
+    let spawns = level.entities.iter().map(|entity| entity.pos);
+    for pos in spawns.chain(level.prefabs.iter().map(|spawn| spawn.pos)) {
+        let pos = Vec2::from(pos).floor().as_ivec2();
+        if !is_reachable(pos) {
+            diagnostics.push(diagnostic(pos, DiagnosticKind::UnreachableSpawn));
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(MapError::Invalid(diagnostics))
+    }
+}
+
+fn diagnostic(pos: IVec2, kind: DiagnosticKind) -> Diagnostic {
+    Diagnostic {
+        row: pos.y.max(0) as usize,
+        col: pos.x.max(0) as usize,
+        kind,
+    }
+}
+
+fn is_frame(tile_map: &TileMap, pos: IVec2) -> bool {
+    tile_map.get_tile(pos).is_some_and(|tile| {
+        !tile.passable && matches!(tile.tile_type, TileType::Wall | TileType::Subwall(..))
+    })
+}
+
+fn is_walkable(tile_map: &TileMap, pos: IVec2) -> bool {
+    match tile_map.get_tile(pos) {
+        None => true,
+        Some(tile) => {
+            tile.passable
+                || tile.elevation >= HEAD_HEIGHT
+                || matches!(tile.tile_type, TileType::Door(..))
+        }
+    }
+}
+
+/// Cells reachable from `start` through walkable cells, indexed like the map.
+fn flood_fill(tile_map: &TileMap, start: IVec2) -> Vec<bool> {
+    let (width, height) = (tile_map.width as i32, tile_map.height as i32);
+    let mut reachable = vec![false; tile_map.width * tile_map.height];
+    let in_bounds = |pos: IVec2| 0 <= pos.x && pos.x < width && 0 <= pos.y && pos.y < height;
+    if !in_bounds(start) {
+        return reachable;
+    }
+
+    let mut queue = VecDeque::from([start]);
+    reachable[(start.x + start.y * width) as usize] = true;
+    while let Some(pos) = queue.pop_front() {
+        for step in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let next = pos + step;
+            let index = (next.x + next.y * width) as usize;
+            if in_bounds(next) && !reachable[index] && is_walkable(tile_map, next) {
+                reachable[index] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::tile_map::{default_legend, parse_grid, Surfaces};
+
+    /// Parses and validates `rows` like a bare ASCII map.
+    fn validate(rows: &[&str]) -> Result<(), MapError> {
+        let tile_map = parse_grid(rows, &default_legend())?;
+        validate_level(&Level::from_tile_map(tile_map, None, Vec::new(), Vec::new(), None)?)
+    }
+
+    fn diagnostics(result: Result<(), MapError>) -> Vec<Diagnostic> {
+        match result {
+            Err(MapError::Invalid(diagnostics)) => diagnostics,
+            Err(error) => panic!("expected diagnostics, got {error}"),
+            Ok(()) => panic!("expected diagnostics, the map is valid"),
+        }
+    }
+
+    fn at(row: usize, col: usize, kind: DiagnosticKind) -> Diagnostic {
+        Diagnostic { row, col, kind }
+    }
+
+    #[test]
+    fn rejects_empty_maps_and_maps_without_a_player() {
+        assert!(matches!(validate(&[]), Err(MapError::Empty)));
+        assert!(matches!(
+            validate(&["111", "1 1", "111"]),
+            Err(MapError::NoPlayerStart)
+        ));
+    }
+
+    #[test]
+    fn reports_ragged_rows_where_they_end() {
+        let diagnostics = diagnostics(validate(&["1111", "1P1", "1  11", "1111"]));
+        assert_eq!(
+            diagnostics,
+            [
+                at(1, 3, DiagnosticKind::RaggedRow { expected: 4, found: 3 }),
+                at(2, 4, DiagnosticKind::RaggedRow { expected: 4, found: 5 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_glyphs() {
+        let diagnostics = diagnostics(validate(&["111", "1P1", "1x1", "111"]));
+        assert_eq!(diagnostics, [at(2, 1, DiagnosticKind::UnknownGlyph('x'))]);
+    }
+
+    #[test]
+    fn reports_reachable_cells_on_the_border() {
+        let diagnostics = diagnostics(validate(&["1 1", "1P1", "111"]));
+        assert_eq!(diagnostics, [at(0, 1, DiagnosticKind::OpenBorder)]);
+    }
+
+    #[test]
+    fn reports_doors_without_a_wall_on_both_sides() {
+        let diagnostics = diagnostics(validate(&[
+            "1111111",
+            "1P    1",
+            "11=1= 1",
+            "1/    1",
+            "11 /  1",
+            "1111111",
+        ]));
+        assert_eq!(
+            diagnostics,
+            [
+                at(2, 4, DiagnosticKind::UnframedDoor),
+                at(4, 3, DiagnosticKind::UnframedDoor),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_spawns_walled_off_from_the_player() {
+        let diagnostics = diagnostics(validate(&["11111", "1P1E1", "11111"]));
+        assert_eq!(diagnostics, [at(1, 3, DiagnosticKind::UnreachableSpawn)]);
+        assert!(validate(&["11111", "1P E1", "11111"]).is_ok());
+    }
+
+    #[test]
+    fn reports_surface_grids_that_dont_fit_the_map() {
+        let mut tile_map = parse_grid(&["111", "1P1", "111"], &default_legend()).unwrap();
+        let legend = HashMap::from([(
+            'g',
+            Surfaces {
+                floor: "assets/grass.png",
+                ceiling: None,
+            },
+        )]);
+        assert!(tile_map.parse_surfaces(&["   ", " g ", "   "], &legend).is_ok());
+        let diagnostics = diagnostics(tile_map.parse_surfaces(&["   ", " g", "   ", " "], &legend));
+        assert_eq!(
+            diagnostics,
+            [
+                at(3, 0, DiagnosticKind::WrongRowCount { expected: 3, found: 4 }),
+                at(1, 2, DiagnosticKind::RaggedRow { expected: 3, found: 2 }),
+            ]
+        );
+    }
+}